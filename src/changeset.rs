@@ -1,10 +1,19 @@
 use std::fs::File;
 use std::io::{BufReader, BufRead};
-use std::path::{Path, PathBuf};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
 use std::io::Write;
 
 use anyhow::Result;
-use std::collections::BTreeMap;
+use serde_json::json;
+use yansi::Paint;
+
+use crate::escape::{escape, unescape};
+use crate::journal::Journal;
+use crate::order::Order;
+use crate::routes::Router;
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Debug, Clone)]
 pub enum Action {
@@ -25,20 +34,31 @@ impl<S> From<S> for Action
             return Action::Ignore(s.trim().to_string());
         }
 
-        return Action::Move(PathBuf::from(s));
+        return Action::Move(unescape(s));
     }
 }
 
 impl std::fmt::Display for Action {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         return match self {
-            Action::Move(target) => write!(f, "{}", target.display()),
+            Action::Move(target) => write!(f, "{}", escape(target)),
             Action::Delete => write!(f, ""),
             Action::Ignore(comment) => write!(f, " {}", comment),
         };
     }
 }
 
+impl Action {
+    /// Returns the structured representation of the action used for JSON output.
+    pub fn to_json(&self) -> serde_json::Value {
+        return match self {
+            Action::Move(target) => json!({ "action": "move", "target": escape(target) }),
+            Action::Delete => json!({ "action": "delete" }),
+            Action::Ignore(comment) => json!({ "action": "ignore", "comment": comment }),
+        };
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ChangeSet {
     workspace: Workspace,
@@ -56,15 +76,7 @@ impl ChangeSet {
     }
 
     pub fn export(&self) -> Result<()> {
-        let mut sources = File::create(self.workspace.sources_path())?;
-        let mut targets = File::create(self.workspace.targets_path())?;
-
-        for (source, target) in self.records.iter() {
-            writeln!(sources, "{}", source.display())?;
-            writeln!(targets, "{}", target)?;
-        }
-
-        return Ok(());
+        return export(&self.workspace, &self.records, &[], &[]);
     }
 
     pub fn workspace(&self) -> &Workspace {
@@ -79,22 +91,361 @@ impl ChangeSet {
         return &self.records;
     }
 
+    pub fn records_mut(&mut self) -> &mut BTreeMap<PathBuf, Action> {
+        return &mut self.records;
+    }
+
+    /// The records in the order of the change set files along with their IDs.
+    pub fn ordered(&self) -> Vec<(usize, &PathBuf, &Action)> {
+        return ordered(&self.workspace, &self.records);
+    }
+
+    /// Finds all targets which are assigned to more than one source.
+    ///
+    /// Targets are compared by the location they are routed to, as different targets may end up
+    /// at the same location. Returns each conflicting location with the record IDs and paths of
+    /// the sources moved to it.
+    pub fn duplicate_targets(&self, router: &Router) -> Result<BTreeMap<PathBuf, Vec<(usize, &Path)>>> {
+        let mut targets = BTreeMap::<_, Vec<_>>::new();
+        for (id, source, action) in self.ordered() {
+            if let Action::Move(target) = action {
+                targets.entry(router.locate(source, target)?)
+                    .or_default()
+                    .push((id, source.as_path()));
+            }
+        }
+
+        targets.retain(|_, sources| sources.len() > 1);
+
+        return Ok(targets);
+    }
+
+    /// Runs all safety checks on the change set.
+    ///
+    /// Returns all problems found in one pass.
+    pub fn validate(&self, options: &Validation) -> Result<Vec<Problem>> {
+        let mut problems = Vec::new();
+
+        // Without routes, all targets are relative to the target directory
+        let router = match options.router {
+            Some(router) => router.clone(),
+            None => Router::new(options.target.unwrap_or(self.path())),
+        };
+
+        for (target, sources) in self.duplicate_targets(&router)? {
+            problems.push(Problem::DuplicateTarget {
+                target,
+                sources: sources.into_iter()
+                    .map(|(id, source)| (id, source.to_path_buf()))
+                    .collect(),
+            });
+        }
+
+        for (id, source, action) in self.ordered() {
+            let path = match action {
+                Action::Move(path) => path,
+                Action::Delete | Action::Ignore(_) => continue,
+            };
+
+            let (source, path) = (source.clone(), path.clone());
+
+            // Absolute targets are not joined to the target directory and can not escape it
+            let absolute = path.is_absolute();
+
+            if absolute && !options.allow_absolute {
+                problems.push(Problem::AbsoluteTarget(id, source, path));
+            } else if is_dir(&path) && !is_dir(&source) {
+                problems.push(Problem::DirectoryTarget(id, source, path));
+            } else if path.to_string_lossy().trim_start_matches('/').trim_end_matches('/').split('/').any(str::is_empty) {
+                problems.push(Problem::EmptyComponent(id, source, path));
+            } else if !absolute && !options.allow_outside && escapes(&path) {
+                problems.push(Problem::OutsideTarget(id, source, path));
+            } else if let Some(reason) = options.portable_names.then(|| non_portable(&path)).flatten() {
+                problems.push(Problem::NonPortableName(id, source, path, reason));
+            } else if options.target.is_some_and(|target| target.join(&path).symlink_metadata().is_ok()) {
+                problems.push(Problem::ExistingTarget(id, source, path));
+            }
+        }
+
+        return Ok(problems);
+    }
+
     pub fn split(self) -> (Workspace, BTreeMap<PathBuf, Action>) {
         return (self.workspace, self.records);
     }
 }
 
+/// Returns the records in the order of the workspace along with their IDs, which are assigned
+/// in that order starting with 1.
+///
+/// Orders depending on the files keep the records in the order they had on import, as the files
+/// may have changed since. Records added since then follow the imported ones.
+fn ordered<'a>(workspace: &Workspace, records: &'a BTreeMap<PathBuf, Action>) -> Vec<(usize, &'a PathBuf, &'a Action)> {
+    let mut records = workspace.order().sort(workspace.path(), records);
+
+    if workspace.order().is_volatile() && !workspace.ids.is_empty() {
+        records.sort_by_key(|(source, _)| workspace.ids.get(*source).copied().unwrap_or(usize::MAX));
+    }
+
+    return records.into_iter()
+        .zip(1..)
+        .map(|((source, action), id)| (id, source, action))
+        .collect();
+}
+
+/// Writes the records and the unmapped lines to the workspace.
+///
+/// Each line is prefixed with the ID of the record, which is used to match up sources and targets
+/// on import. IDs are assigned in the order of the workspace and padded to equal width. Unmapped sources and targets get
+/// IDs of their own following the records, so they stay unmapped on the next import.
+fn export(workspace: &Workspace,
+          records: &BTreeMap<PathBuf, Action>,
+          unmapped_sources: &[(usize, PathBuf)],
+          unmapped_targets: &[(usize, Action)]) -> Result<()> {
+    let count = records.len() + unmapped_sources.len() + unmapped_targets.len();
+    let width = count.to_string().len();
+
+    let records = ordered(workspace, records);
+    let mut ids = records.len() + 1..;
+
+    // Named change sets are kept in a directory of their own
+    std::fs::create_dir_all(&workspace.state)?;
+
+    let mut meta = format!("version {}\n", VERSION);
+    if workspace.order != Order::default() {
+        meta.push_str(&format!("order {}\n", workspace.order));
+    }
+    std::fs::write(workspace.meta_path(), meta)?;
+
+    match workspace.format() {
+        Format::Split => {
+            let mut sources = File::create(workspace.sources_path())?;
+            let mut targets = File::create(workspace.targets_path())?;
+
+            for (id, source, target) in records.iter() {
+                writeln!(sources, "{:0width$}\t{}", id, escape(source), width = width)?;
+                writeln!(targets, "{:0width$}\t{}", id, target, width = width)?;
+            }
+
+            for ((_, source), id) in unmapped_sources.iter().zip(&mut ids) {
+                writeln!(sources, "{:0width$}\t{}", id, escape(source), width = width)?;
+            }
+
+            for ((_, target), id) in unmapped_targets.iter().zip(&mut ids) {
+                writeln!(targets, "{:0width$}\t{}", id, target, width = width)?;
+            }
+        }
+
+        Format::Combined => {
+            let mut changes = File::create(workspace.changes_path())?;
+
+            for (id, source, target) in records.iter() {
+                writeln!(changes, "{:0width$}\t{}\t{}", id, escape(source), target, width = width)?;
+            }
+
+            // The combined format has no unmapped targets
+            for ((_, source), id) in unmapped_sources.iter().zip(&mut ids) {
+                writeln!(changes, "{:0width$}\t{}", id, escape(source), width = width)?;
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+/// The version of the change set file format written by this release.
+///
+/// Version 0 is the unversioned format without record IDs and escaping.
+pub const VERSION: u32 = 1;
+
+/// Splits a line into the leading record ID and the remaining content.
+///
+/// A line consisting of the ID only has an empty content.
+fn parse_record(line: &str) -> Option<(usize, &str)> {
+    let (id, content) = line.split_once('\t').unwrap_or((line, ""));
+    let id = id.trim().parse().ok()?;
+
+    return Some((id, content));
+}
+
+/// Options for validating a change set.
+#[derive(Debug, Clone, Default)]
+pub struct Validation<'a> {
+    /// The target directory to check for existing files
+    pub target: Option<&'a Path>,
+
+    /// The routes of the records to other target directories
+    pub router: Option<&'a Router>,
+
+    /// Allow targets resolving outside of the target directory
+    pub allow_outside: bool,
+
+    /// Allow absolute targets which are not joined to the target directory
+    pub allow_absolute: bool,
+
+    /// Reject target names which are not valid on Windows file systems like NTFS or exFAT
+    pub portable_names: bool,
+}
+
+/// Checks if a path contains names which are not valid on Windows file systems.
+///
+/// Returns the reason for the first offending path component.
+fn non_portable(path: &Path) -> Option<&'static str> {
+    const RESERVED: &[&str] = &[
+        "CON", "PRN", "AUX", "NUL",
+        "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+        "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+
+    for component in path.components() {
+        let name = match component {
+            Component::Normal(name) => name.to_string_lossy(),
+            _ => continue,
+        };
+
+        if name.chars().any(|c| c.is_control() || "<>:\"|?*\\".contains(c)) {
+            return Some("name contains a reserved character");
+        }
+
+        if name.ends_with('.') || name.ends_with(' ') {
+            return Some("name ends with a dot or space");
+        }
+
+        // Reserved names are invalid with any extension, too
+        let stem = name.split('.').next().unwrap_or_default();
+        if RESERVED.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+            return Some("name is reserved");
+        }
+    }
+
+    return None;
+}
+
+/// Checks if a record refers to a directory, which is marked by a trailing slash.
+pub fn is_dir(path: &Path) -> bool {
+    return path.as_os_str().as_bytes().ends_with(b"/");
+}
+
+/// Checks if a relative path leaves the directory it is relative to by lexically resolving all
+/// `..` components.
+fn escapes(path: &Path) -> bool {
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return true,
+            },
+            Component::Normal(_) => depth += 1,
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+
+    return false;
+}
+
+/// A problem found while validating a change set.
+#[derive(Debug, Clone)]
+pub enum Problem {
+    /// Multiple sources are moved to the same target
+    DuplicateTarget { target: PathBuf, sources: Vec<(usize, PathBuf)> },
+
+    /// The target is an absolute path
+    AbsoluteTarget(usize, PathBuf, PathBuf),
+
+    /// The target contains an empty path component
+    EmptyComponent(usize, PathBuf, PathBuf),
+
+    /// The target of a file is marked as directory
+    DirectoryTarget(usize, PathBuf, PathBuf),
+
+    /// The target resolves outside of the target directory
+    OutsideTarget(usize, PathBuf, PathBuf),
+
+    /// The target is not a valid name on Windows file systems
+    NonPortableName(usize, PathBuf, PathBuf, &'static str),
+
+    /// The target exists already
+    ExistingTarget(usize, PathBuf, PathBuf),
+}
+
+impl Problem {
+    /// Returns true if the change set can not be executed with this problem.
+    ///
+    /// Existing targets are resolved during execution depending on the conflict policy.
+    pub fn is_error(&self) -> bool {
+        return !matches!(self, Problem::ExistingTarget(..));
+    }
+
+    /// Prints the problem along with the affected records.
+    pub fn print(&self) {
+        let symbol = if self.is_error() {
+            Paint::red("✗").bold()
+        } else {
+            Paint::yellow("⚠").bold()
+        };
+
+        match self {
+            Problem::DuplicateTarget { target, sources } => {
+                eprintln!("{} {}: multiple sources moved to the same target", symbol, target.display());
+                for (id, source) in sources.iter() {
+                    eprintln!("  {:>6}: {}", id, source.display());
+                }
+            }
+            Problem::AbsoluteTarget(id, source, target) => {
+                eprintln!("{} {}: target is absolute", symbol, target.display());
+                eprintln!("  {:>6}: {}", id, source.display());
+            }
+            Problem::EmptyComponent(id, source, target) => {
+                eprintln!("{} {}: target contains an empty path component", symbol, target.display());
+                eprintln!("  {:>6}: {}", id, source.display());
+            }
+            Problem::DirectoryTarget(id, source, target) => {
+                eprintln!("{} {}: target of a file ends with a slash", symbol, target.display());
+                eprintln!("  {:>6}: {}", id, source.display());
+            }
+            Problem::OutsideTarget(id, source, target) => {
+                eprintln!("{} {}: target is outside of the target directory", symbol, target.display());
+                eprintln!("  {:>6}: {}", id, source.display());
+            }
+            Problem::NonPortableName(id, source, target, reason) => {
+                eprintln!("{} {}: {}", symbol, target.display(), reason);
+                eprintln!("  {:>6}: {}", id, source.display());
+            }
+            Problem::ExistingTarget(id, source, target) => {
+                eprintln!("{} {}: target exists already", symbol, target.display());
+                eprintln!("  {:>6}: {}", id, source.display());
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ChangeSetImport {
     workspace: Workspace,
 
     records: BTreeMap<PathBuf, Action>,
 
-    unmapped_sources: Vec<PathBuf>,
-    unmapped_targets: Vec<Action>,
+    /// Sources without a target along with their line numbers
+    unmapped_sources: Vec<(usize, PathBuf)>,
+
+    /// Targets without a source along with their line numbers
+    unmapped_targets: Vec<(usize, Action)>,
 }
 
 impl ChangeSetImport {
+    pub fn create(workspace: Workspace,
+                  records: BTreeMap<PathBuf, Action>,
+                  unmapped_sources: Vec<(usize, PathBuf)>,
+                  unmapped_targets: Vec<(usize, Action)>) -> Self {
+        return Self {
+            workspace,
+            records,
+            unmapped_sources,
+            unmapped_targets,
+        };
+    }
+
     pub fn empty(workspace: Workspace) -> Self {
         return Self {
             workspace,
@@ -104,23 +455,59 @@ impl ChangeSetImport {
         };
     }
 
+    /// Imports the change set from the workspace.
+    ///
+    /// Workspaces using an older version of the file format are upgraded to the current version.
     pub fn import(workingdir: Workspace) -> Result<Self> {
+        let version = workingdir.version()?;
+
+        if version > VERSION {
+            return Err(anyhow::anyhow!("Unsupported workspace version {}, please upgrade mmv", version));
+        }
+
+        if version < VERSION {
+            let result = match version {
+                0 => Self::import_legacy(workingdir)?,
+                _ => return Err(anyhow::anyhow!("Unsupported workspace version {}", version)),
+            };
+
+            result.export()?;
+
+            info!("Upgraded workspace from version {} to {}", version, VERSION);
+
+            return Ok(result);
+        }
+
+        return match workingdir.format() {
+            Format::Split => Self::import_split(workingdir),
+            Format::Combined => Self::import_combined(workingdir),
+        };
+    }
+
+    /// Imports the unversioned format having the sources and targets aligned by line number and
+    /// no escaping applied.
+    fn import_legacy(workingdir: Workspace) -> Result<Self> {
         let mut sources = BufReader::new(File::open(workingdir.sources_path())?).lines()
-            .map::<Result<_>, _>(|line| Ok(PathBuf::from(line?)));
+            .enumerate();
         let mut targets = BufReader::new(File::open(workingdir.targets_path())?).lines()
-            .map::<Result<_>, _>(|line| Ok(Action::from(line?)));
+            .enumerate();
+
+        let target = |line: String| match Action::from(&line) {
+            Action::Move(_) => Action::Move(PathBuf::from(line)),
+            action => action,
+        };
 
         let mut result = Self::empty(workingdir);
         loop {
             match (sources.next(), targets.next()) {
-                (Some(source), Some(target)) => {
-                    result.records.insert(source?, target?);
+                (Some((_, source)), Some((_, line))) => {
+                    result.records.insert(PathBuf::from(source?), target(line?));
                 }
-                (Some(source), None) => {
-                    result.unmapped_sources.push(source?);
+                (Some((line, source)), None) => {
+                    result.unmapped_sources.push((line + 1, PathBuf::from(source?)));
                 }
-                (None, Some(target)) => {
-                    result.unmapped_targets.push(target?);
+                (None, Some((line, content))) => {
+                    result.unmapped_targets.push((line + 1, target(content?)));
                 }
                 (None, None) => {
                     break;
@@ -131,20 +518,140 @@ impl ChangeSetImport {
         return Ok(result);
     }
 
-    pub fn workspace(&self) -> &Workspace {
-        return &self.workspace;
+    fn import_split(workingdir: Workspace) -> Result<Self> {
+        let mut sources = BTreeMap::new();
+        let mut result = Self::empty(workingdir);
+
+        for (line, content) in BufReader::new(File::open(result.workspace.sources_path())?).lines().enumerate() {
+            let content = content?;
+            match parse_record(&content) {
+                Some((id, source)) if !sources.contains_key(&id) => {
+                    sources.insert(id, (line + 1, unescape(source)));
+                }
+                Some((_, source)) => {
+                    result.unmapped_sources.push((line + 1, unescape(source)));
+                }
+                None => {
+                    result.unmapped_sources.push((line + 1, unescape(&content)));
+                }
+            }
+        }
+
+        for (line, content) in BufReader::new(File::open(result.workspace.targets_path())?).lines().enumerate() {
+            let content = content?;
+            match parse_record(&content) {
+                Some((id, target)) if sources.contains_key(&id) => {
+                    let (_, source) = sources.remove(&id).expect("No source");
+                    result.workspace.ids.insert(source.clone(), id);
+                    result.records.insert(source, Action::from(target));
+                }
+                Some((_, target)) => {
+                    result.unmapped_targets.push((line + 1, Action::from(target)));
+                }
+                None => {
+                    result.unmapped_targets.push((line + 1, Action::from(content)));
+                }
+            }
+        }
+
+        // All sources left have not been assigned to a target
+        result.unmapped_sources.extend(sources.into_values());
+        result.unmapped_sources.sort_by_key(|(line, _)| *line);
+
+        return Ok(result);
     }
 
-    pub fn path(&self) -> &Path {
-        return self.workspace.path();
+    /// Imports the combined format having the ID, the source and the target separated by tabs on
+    /// each line. Lines without a target are treated as sources without a target.
+    fn import_combined(workingdir: Workspace) -> Result<Self> {
+        let mut ids = BTreeSet::new();
+        let mut result = Self::empty(workingdir);
+
+        for (line, content) in BufReader::new(File::open(result.workspace.changes_path())?).lines().enumerate() {
+            let content = content?;
+            let (id, record) = match parse_record(&content) {
+                Some((id, record)) => (Some(id), record),
+                None => (None, content.as_str()),
+            };
+
+            match (id, record.split_once('\t')) {
+                (Some(id), Some((source, target))) if ids.insert(id) => {
+                    result.workspace.ids.insert(unescape(source), id);
+                    result.records.insert(unescape(source), Action::from(target));
+                }
+                (_, Some((source, _))) => {
+                    result.unmapped_sources.push((line + 1, unescape(source)));
+                }
+                (_, None) => {
+                    result.unmapped_sources.push((line + 1, unescape(record)));
+                }
+            }
+        }
+
+        return Ok(result);
+    }
+
+    pub fn workspace(&self) -> &Workspace {
+        return &self.workspace;
     }
 
     pub fn records(&self) -> &BTreeMap<PathBuf, Action> {
         return &self.records;
     }
 
-    pub fn records_mut(&mut self) -> &mut BTreeMap<PathBuf, Action> {
-        return &mut self.records;
+    /// The records in the order of the change set files along with their IDs.
+    pub fn ordered(&self) -> Vec<(usize, &PathBuf, &Action)> {
+        return ordered(&self.workspace, &self.records);
+    }
+
+    pub fn unmapped_sources(&self) -> &[(usize, PathBuf)] {
+        return &self.unmapped_sources;
+    }
+
+    pub fn unmapped_targets(&self) -> &[(usize, Action)] {
+        return &self.unmapped_targets;
+    }
+
+    /// Writes the records and the unmapped lines back to the workspace.
+    pub fn export(&self) -> Result<()> {
+        return export(&self.workspace, &self.records, &self.unmapped_sources, &self.unmapped_targets);
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn split(self) -> (Workspace, BTreeMap<PathBuf, Action>, Vec<(usize, PathBuf)>, Vec<(usize, Action)>) {
+        return (self.workspace, self.records, self.unmapped_sources, self.unmapped_targets);
+    }
+
+    /// Returns the structured representation of the change set used for JSON output.
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        let records = self.records.iter()
+            .map(|(source, action)| {
+                let mut record = action.to_json();
+                record["source"] = json!(escape(source));
+                return record;
+            })
+            .collect::<Vec<_>>();
+
+        let unmapped_sources = self.unmapped_sources.iter()
+            .map(|(line, source)| json!({ "line": line, "source": escape(source) }))
+            .collect::<Vec<_>>();
+
+        let unmapped_targets = self.unmapped_targets.iter()
+            .map(|(line, action)| {
+                let mut target = action.to_json();
+                target["line"] = json!(line);
+                return target;
+            })
+            .collect::<Vec<_>>();
+
+        return Ok(json!({
+            "workspace": escape(&std::path::absolute(self.workspace.path())?),
+            "format": self.workspace.format().to_string(),
+            "clean": self.is_clean(),
+            "records": records,
+            "unmapped_sources": unmapped_sources,
+            "unmapped_targets": unmapped_targets,
+        }));
     }
 
     pub fn is_clean(&self) -> bool {
@@ -163,20 +670,183 @@ impl ChangeSetImport {
     }
 }
 
+/// The layout of the change set files in a workspace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Sources and targets are stored in separate files aligned by line number
+    Split,
+
+    /// Sources and targets are stored tab-separated in a single file
+    Combined,
+}
+
+impl std::str::FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "split" => Ok(Format::Split),
+            "combined" => Ok(Format::Combined),
+            _ => Err(anyhow::anyhow!("Invalid format: {}", s)),
+        };
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Format::Split => write!(f, "split"),
+            Format::Combined => write!(f, "combined"),
+        };
+    }
+}
+
+/// The name of the change set selected using `--changeset`, the default change set is used if none
+/// is selected.
+static SELECTED: OnceLock<String> = OnceLock::new();
+
+/// Selects the named change set used by all commands.
+pub fn select(name: &str) -> Result<()> {
+    validate_name(name)?;
+
+    SELECTED.set(name.to_string())
+        .map_err(|_| anyhow::anyhow!("Change set already selected"))?;
+
+    return Ok(());
+}
+
+/// Checks that the name of a change set can be used as a directory name.
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        return Err(anyhow::anyhow!("Invalid change set name: {}", name));
+    }
+
+    return Ok(());
+}
+
 #[derive(Debug, Clone)]
 pub struct Workspace {
     path: PathBuf,
+
+    /// The directory containing the ignore list and the named change sets, which is either the
+    /// workspace itself or a directory outside of it
+    root: PathBuf,
+
+    /// The directory containing the files of the change set
+    state: PathBuf,
+
+    format: Format,
+
+    order: Order,
+
+    /// The IDs of the records read from the change set files, which are kept for orders depending
+    /// on the files
+    ids: BTreeMap<PathBuf, usize>,
 }
 
 impl Workspace {
+    /// The workspace of the default change set in the given directory.
     pub fn at(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let root = Self::root(&path);
+        return Self::with_state(path, root.clone(), root);
+    }
+
+    /// The workspace of a named change set, which is kept in the `.mmv.stash` directory.
+    pub fn named(path: impl AsRef<Path>, name: &str) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let root = Self::root(&path);
+        let state = root.join(".mmv.stash").join(name);
+        return Self::with_state(path, root, state);
+    }
+
+    /// The workspace of the change set selected on the command line.
+    pub fn selected(path: impl AsRef<Path>) -> Self {
+        return match SELECTED.get() {
+            Some(name) => Self::named(path, name),
+            None => Self::at(path),
+        };
+    }
+
+    /// The directory outside of the workspace keeping its state in
+    /// `$XDG_STATE_HOME/mmv/<hash-of-path>`, which is used instead of the workspace if it exists.
+    pub fn external(path: impl AsRef<Path>) -> Option<PathBuf> {
+        let state = std::env::var_os("XDG_STATE_HOME")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))?;
+
+        let path = std::fs::canonicalize(path).ok()?;
+        let hash = blake3::hash(path.as_os_str().as_bytes()).to_hex();
+
+        return Some(state.join("mmv").join(&hash[..32]));
+    }
+
+    fn root(path: &Path) -> PathBuf {
+        return match Self::external(path) {
+            Some(external) if external.is_dir() => external,
+            _ => path.to_path_buf(),
+        };
+    }
+
+    fn with_state(path: PathBuf, root: PathBuf, state: PathBuf) -> Self {
+        // The combined format is used if the workspace has been initialized that way
+        let format = if state.join(".mmv.changes").is_file() {
+            Format::Combined
+        } else {
+            Format::Split
+        };
+
+        // The order is kept in the metadata and defaults to the path order
+        let order = std::fs::read_to_string(state.join(".mmv.meta")).ok()
+            .and_then(|meta| meta.lines()
+                .find_map(|line| line.strip_prefix("order "))
+                .and_then(|order| order.trim().parse().ok()))
+            .unwrap_or_default();
+
         return Self {
-            path: path.as_ref().to_path_buf(),
+            path,
+            root,
+            state,
+            format,
+            order,
+            ids: BTreeMap::new(),
         };
     }
 
+    /// Changes the order of the records, which is applied on the next export.
+    pub fn with_order(self, order: Order) -> Self {
+        // The imported IDs are only kept if the order stays the same
+        let ids = if order == self.order { self.ids } else { BTreeMap::new() };
+
+        return Self {
+            order,
+            ids,
+            ..self
+        };
+    }
+
+    /// Switches the workspace to the given format and removes the files of the other format.
+    pub fn convert(self, format: Format) -> Result<Self> {
+        let stale = match format {
+            Format::Split => vec![self.changes_path()],
+            Format::Combined => vec![self.sources_path(), self.targets_path()],
+        };
+
+        for path in stale {
+            if path.is_file() {
+                std::fs::remove_file(path)?;
+            }
+        }
+
+        return Ok(Self {
+            format,
+            ..self
+        });
+    }
+
     pub fn open(path: impl AsRef<Path>) -> Option<Self> {
-        let workspace = Self::at(path);
+        let workspace = Self::selected(path);
 
         if workspace.is_initialized() {
             return Some(workspace);
@@ -185,11 +855,46 @@ impl Workspace {
         }
     }
 
-    pub fn is_initialized(&self) -> bool {
-        let sources = self.sources_path();
-        let targets = self.targets_path();
+    /// Removes the change set along with the journals and the persisted filter, leaving the
+    /// directory uninitialized. The ignore list and the configuration are kept.
+    pub fn remove(self) -> Result<()> {
+        let previous_journals = (1..=Journal::PREVIOUS)
+            .map(|n| self.previous_journal_path(n));
+
+        let paths = [
+            self.sources_path(),
+            self.targets_path(),
+            self.changes_path(),
+            self.meta_path(),
+            self.journal_path(),
+            self.filter_path(),
+            self.fingerprints_path(),
+        ];
+
+        for path in paths.iter().cloned().chain(previous_journals) {
+            if path.is_file() {
+                std::fs::remove_file(path)?;
+            }
+        }
 
-        return sources.is_file() && targets.is_file();
+        // The directory of a named change set or an external workspace is removed along with it,
+        // unless it still contains the ignore list or other change sets
+        if self.state != self.path {
+            match std::fs::remove_dir(&self.state) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::DirectoryNotEmpty => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        return Ok(());
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        return match self.format {
+            Format::Split => self.sources_path().is_file() && self.targets_path().is_file(),
+            Format::Combined => self.changes_path().is_file(),
+        };
     }
 
     pub fn import(self) -> Result<ChangeSetImport> {
@@ -200,12 +905,123 @@ impl Workspace {
         return &self.path;
     }
 
+    pub fn format(&self) -> Format {
+        return self.format;
+    }
+
+    pub fn order(&self) -> Order {
+        return self.order;
+    }
+
     pub fn sources_path(&self) -> PathBuf {
-        return self.path.join(".mmv.sources");
+        return self.state.join(".mmv.sources");
     }
 
     pub fn targets_path(&self) -> PathBuf {
-        return self.path.join(".mmv.targets");
+        return self.state.join(".mmv.targets");
+    }
+
+    pub fn changes_path(&self) -> PathBuf {
+        return self.state.join(".mmv.changes");
+    }
+
+    pub fn meta_path(&self) -> PathBuf {
+        return self.state.join(".mmv.meta");
+    }
+
+    /// Reads the version of the change set file format from the workspace metadata.
+    ///
+    /// Split workspaces without metadata use the unversioned format, which never existed for
+    /// combined workspaces.
+    pub fn version(&self) -> Result<u32> {
+        let meta = match std::fs::read_to_string(self.meta_path()) {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(match self.format {
+                    Format::Split => 0,
+                    Format::Combined => VERSION,
+                });
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        for line in meta.lines() {
+            if let Some(("version", version)) = line.split_once(' ') {
+                return version.trim().parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid workspace version: {}", version));
+            }
+        }
+
+        return Err(anyhow::anyhow!("Missing workspace version in {}", self.meta_path().display()));
+    }
+
+    pub fn ignore_path(&self) -> PathBuf {
+        return self.root.join(".mmv.ignore");
+    }
+
+    pub fn filter_path(&self) -> PathBuf {
+        return self.state.join(".mmv.filter");
+    }
+
+    pub fn fingerprints_path(&self) -> PathBuf {
+        return self.state.join(".mmv.fingerprints");
+    }
+
+    pub fn journal_path(&self) -> PathBuf {
+        return self.state.join(".mmv.journal");
+    }
+
+    /// The journal of the n-th execution before the last one.
+    pub fn previous_journal_path(&self, n: usize) -> PathBuf {
+        return self.state.join(format!(".mmv.journal.{}", n));
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        return self.path.join(".mmv.toml");
+    }
+
+    /// Copies the files of the change set to another workspace, replacing its change set.
+    pub fn copy_to(&self, other: &Workspace) -> Result<Workspace> {
+        std::fs::create_dir_all(&other.state)?;
+
+        let files = [
+            (self.sources_path(), other.sources_path()),
+            (self.targets_path(), other.targets_path()),
+            (self.changes_path(), other.changes_path()),
+            (self.meta_path(), other.meta_path()),
+            (self.filter_path(), other.filter_path()),
+            (self.fingerprints_path(), other.fingerprints_path()),
+        ];
+
+        for (source, target) in files.iter() {
+            if source.is_file() {
+                std::fs::copy(source, target)?;
+            } else if target.is_file() {
+                std::fs::remove_file(target)?;
+            }
+        }
+
+        return Ok(Self::with_state(other.path.clone(), other.root.clone(), other.state.clone()));
+    }
+
+    /// The names of all named change sets in the directory.
+    pub fn names(path: impl AsRef<Path>) -> Result<Vec<String>> {
+        let stash = Self::root(path.as_ref()).join(".mmv.stash");
+        if !stash.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(stash)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+
+        names.sort();
+
+        return Ok(names);
     }
 }
 
@@ -0,0 +1,61 @@
+use std::io::Write;
+use std::path::Path;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use yansi::Paint;
+
+use crate::changeset::Workspace;
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    // An execution can not be undone after the journal is gone
+    if workspace.journal_path().is_file() {
+        warn!("The journals are removed, so the last execution can not be undone anymore");
+    }
+
+    if !matches.is_present("force") && !confirm(&workspace)? {
+        return Ok(());
+    }
+
+    let path = workspace.path().to_path_buf();
+    workspace.remove()?;
+
+    info!("{} {}", Paint::green("Discarded change set in"), path.display());
+
+    return Ok(());
+}
+
+/// Asks the user whether to discard the change set.
+fn confirm(workspace: &Workspace) -> Result<bool, ProgramError> {
+    loop {
+        print!("{} Discard the change set in {}? [y]es / [n]o ", Paint::yellow("?").bold(), workspace.path().display());
+        std::io::stdout().flush()
+            .map_err(anyhow::Error::from)?;
+
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer).map_err(anyhow::Error::from)? == 0 {
+            // Nothing more to read - keep the change set
+            println!();
+            return Ok(false);
+        }
+
+        match answer.trim() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => continue,
+        }
+    }
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("abort")
+        .about("Discards the change set and leaves the directory uninitialized")
+        .arg(Arg::with_name("force")
+            .short("f")
+            .long("force")
+            .takes_value(false)
+            .help("Do not ask for confirmation"));
+}
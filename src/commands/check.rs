@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use yansi::Paint;
+
+use crate::changeset::{Validation, Workspace};
+use crate::config::WorkspaceConfig;
+use crate::routes::Router;
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let target = matches.value_of("target").map(Path::new);
+
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    let changeset = workspace.import()?;
+    let changeset = changeset.clean()
+        .ok_or_else(|| ProgramError::NotClean)?;
+
+    // Targets are routed by the configured routes, as when executing
+    let mut router = Router::new(target.unwrap_or(changeset.path()));
+    for rule in WorkspaceConfig::load(changeset.workspace())?.routes.iter() {
+        router.add(rule)?;
+    }
+
+    let problems = changeset.validate(&Validation {
+        target,
+        router: Some(&router),
+        allow_outside: matches.is_present("allow-outside"),
+        allow_absolute: matches.is_present("allow-absolute"),
+        portable_names: matches.is_present("portable-names"),
+    })?;
+    for problem in problems.iter() {
+        problem.print();
+    }
+
+    let errors = problems.iter().filter(|problem| problem.is_error()).count();
+    let warnings = problems.len() - errors;
+
+    if errors > 0 {
+        eprintln!("{} errors, {} warnings", Paint::red(errors).bold(), Paint::yellow(warnings).bold());
+        return Err(ProgramError::Invalid);
+    }
+
+    if warnings > 0 {
+        info!("{} {} warnings", Paint::green("Change set is valid"), Paint::yellow(warnings).bold());
+    } else {
+        info!("{}", Paint::green("Change set is valid"));
+    }
+
+    return Ok(());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("check")
+        .about("Validates the change set without executing it")
+        .arg(Arg::with_name("target")
+            .value_name("DIR")
+            .help("The target directory to check for existing files")
+            .index(1))
+        .arg(Arg::with_name("allow-outside")
+            .long("allow-outside")
+            .takes_value(false)
+            .help("Allow targets resolving outside of the target directory"))
+        .arg(Arg::with_name("allow-absolute")
+            .long("allow-absolute")
+            .takes_value(false)
+            .help("Allow absolute targets which are not placed in the target directory"))
+        .arg(Arg::with_name("portable-names")
+            .long("portable-names")
+            .takes_value(false)
+            .help("Reject target names which are not valid on Windows file systems"));
+}
@@ -0,0 +1,28 @@
+use std::path::Path;
+
+use clap::{App, Arg, ArgMatches, Shell, SubCommand};
+
+use crate::ProgramError;
+
+pub fn run(_workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let shell = matches.value_of("shell").expect("No shell")
+        .parse::<Shell>()
+        .map_err(|err| anyhow::anyhow!(err))?;
+
+    crate::app().gen_completions_to("mmv", shell, &mut std::io::stdout());
+
+    return Ok(());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("completions")
+        .about("Prints the shell completion script")
+        .long_about("Prints the shell completion script for the given shell to stdout, i.e. \
+            mmv completions bash > /etc/bash_completion.d/mmv")
+        .arg(Arg::with_name("shell")
+            .value_name("SHELL")
+            .help("The shell to generate the script for")
+            .required(true)
+            .possible_values(&Shell::variants())
+            .index(1));
+}
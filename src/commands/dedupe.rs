@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use globset::Glob;
+use indicatif::HumanBytes;
+use yansi::Paint;
+
+use crate::changeset::{is_dir, Action, Workspace};
+use crate::config::Config;
+use crate::hash::HashAlgorithm;
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let filter = matches.value_of("filter")
+        .map(|filter| Glob::new(filter).map(|glob| glob.compile_matcher()))
+        .transpose()
+        .map_err(anyhow::Error::from)?;
+
+    let ignored = matches.is_present("ignored");
+
+    let hash = match matches.value_of("hash") {
+        Some(hash) => hash.parse::<HashAlgorithm>()?,
+        None => Config::load()?.checksum.algorithm.unwrap_or_default(),
+    };
+
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    let changeset = workspace.import()?;
+    let mut changeset = changeset.clean()
+        .ok_or_else(|| ProgramError::NotClean)?;
+
+    let root = changeset.path().to_path_buf();
+
+    // Only files of the same size can be identical, so the others are never hashed
+    let mut sizes = BTreeMap::<u64, Vec<PathBuf>>::new();
+    for (source, action) in changeset.records().iter() {
+        // Ignored records are only marked for deletion if requested
+        match action {
+            Action::Move(_) => {}
+            Action::Ignore(_) if ignored => {}
+            _ => continue,
+        }
+
+        if is_dir(source) {
+            continue;
+        }
+
+        if let Some(filter) = filter.as_ref() {
+            if !filter.is_match(source) {
+                continue;
+            }
+        }
+
+        let metadata = match root.join(source).symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                warn!("{}: {}, skipping", source.display(), err);
+                continue;
+            }
+        };
+
+        if !metadata.is_file() {
+            continue;
+        }
+
+        sizes.entry(metadata.len()).or_default().push(source.clone());
+    }
+
+    let mut groups = BTreeMap::<Vec<u8>, Vec<PathBuf>>::new();
+    for (_, sources) in sizes.into_iter().filter(|(_, sources)| sources.len() > 1) {
+        for source in sources {
+            debug!("hashing {}", source.display());
+            groups.entry(hash.checksum(&root.join(&source))?).or_default().push(source);
+        }
+    }
+
+    // Files with the same checksum are compared byte by byte before deleting any of them, as
+    // different contents may have the same checksum
+    let mut duplicates = Vec::<Vec<PathBuf>>::new();
+    for (_, sources) in groups.into_iter().filter(|(_, sources)| sources.len() > 1) {
+        let mut identical = Vec::<Vec<PathBuf>>::new();
+        for source in sources {
+            let mut found = None;
+            for (i, files) in identical.iter().enumerate() {
+                if same_content(&root.join(&files[0]), &root.join(&source))? {
+                    found = Some(i);
+                    break;
+                }
+            }
+
+            match found {
+                Some(i) => identical[i].push(source),
+                None => identical.push(vec![source]),
+            }
+        }
+
+        duplicates.extend(identical.into_iter().filter(|files| files.len() > 1));
+    }
+
+    let mut count = 0;
+    let mut bytes = 0;
+    for mut sources in duplicates {
+        match matches.value_of("keep").expect("No keep") {
+            "shortest" => sources.sort_by_key(|source| source.as_os_str().len()),
+            "oldest" => sources.sort_by_cached_key(|source| root.join(source).symlink_metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()),
+            _ => unreachable!(),
+        }
+
+        let mut sources = sources.into_iter();
+        let kept = sources.next().expect("No source");
+        info!("{} {}", Paint::new("·").dimmed(), kept.display());
+
+        for source in sources {
+            info!("  {} {}", Paint::red("✕").bold(), source.display());
+
+            bytes += root.join(&source).symlink_metadata().map(|metadata| metadata.len()).unwrap_or_default();
+            count += 1;
+
+            changeset.records_mut().insert(source, Action::Delete);
+        }
+    }
+
+    info!("{} duplicates, {} to free", count, HumanBytes(bytes));
+
+    if !matches.is_present("dry-run") {
+        changeset.export()?;
+    }
+
+    return Ok(());
+}
+
+/// Compares the contents of two files byte by byte.
+fn same_content(a: &Path, b: &Path) -> anyhow::Result<bool> {
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+
+    loop {
+        let a_buffer = a.fill_buf()?;
+        let b_buffer = b.fill_buf()?;
+
+        if a_buffer.is_empty() || b_buffer.is_empty() {
+            return Ok(a_buffer.is_empty() && b_buffer.is_empty());
+        }
+
+        let len = a_buffer.len().min(b_buffer.len());
+        if a_buffer[..len] != b_buffer[..len] {
+            return Ok(false);
+        }
+
+        a.consume(len);
+        b.consume(len);
+    }
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("dedupe")
+        .about("Marks files with identical content for deletion, keeping one of each")
+        .arg(Arg::with_name("keep")
+            .long("keep")
+            .value_name("PREFERENCE")
+            .possible_values(&["shortest", "oldest"])
+            .help("Which file of identical files to keep")
+            .takes_value(true)
+            .default_value("shortest"))
+        .arg(Arg::with_name("filter")
+            .short("f")
+            .long("filter")
+            .value_name("GLOB")
+            .help("Only consider records with a source matching the pattern")
+            .takes_value(true))
+        .arg(Arg::with_name("ignored")
+            .long("ignored")
+            .takes_value(false)
+            .help("Also mark ignored records for deletion, which are left alone otherwise"))
+        .arg(Arg::with_name("hash")
+            .long("hash")
+            .value_name("ALGORITHM")
+            .possible_values(HashAlgorithm::NAMES)
+            .help("The algorithm used to compare the file contents, defaults to the configured one or blake3")
+            .takes_value(true))
+        .arg(Arg::with_name("dry-run")
+            .short("n")
+            .long("dry-run")
+            .takes_value(false)
+            .help("Print the duplicates without updating the change set"));
+}
@@ -0,0 +1,172 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use yansi::Paint;
+
+use crate::changeset::{is_dir, Action, ChangeSet, Workspace};
+use crate::config::Config;
+use crate::escape::escape;
+use crate::hash::HashAlgorithm;
+use crate::ignorelist::IgnoreList;
+use crate::scan::{scan_tree, ScanFilter};
+use crate::ProgramError;
+
+pub fn run(matches: &ArgMatches) -> Result<(), ProgramError> {
+    let old = Path::new(matches.value_of_os("old").expect("No old directory"));
+    let new = Path::new(matches.value_of_os("new").expect("No new directory"));
+
+    let by = matches.value_of("by").expect("No comparison");
+
+    let config = Config::load()?;
+    let hash = match matches.value_of("hash") {
+        Some(hash) => hash.parse::<HashAlgorithm>()?,
+        None => config.checksum.algorithm.unwrap_or_default(),
+    };
+
+    // The change set is created in the old directory, which is transformed into the new one
+    let workspace = Workspace::selected(old);
+
+    if !matches.is_present("force") && workspace.is_initialized() {
+        eprintln!("{} Use -f to reset", Paint::red("Already initialized."));
+        return Ok(());
+    }
+
+    let ignores = IgnoreList::load(&workspace)?
+        .with_global(&config)?;
+    let filter = match ScanFilter::from_matches(matches)? {
+        Some(filter) => filter,
+        None => ScanFilter::configured(&workspace)?,
+    };
+
+    let scan = |root: &Path| -> anyhow::Result<BTreeSet<PathBuf>> {
+        return Ok(scan_tree(root, &filter)?
+            .filter(|path| !is_dir(path) && !ignores.is_ignored(path))
+            .collect());
+    };
+
+    let mut old_files = scan(old)?;
+    let mut new_files = scan(new)?;
+
+    let mut records = BTreeMap::new();
+
+    // Files at the same path are kept where they are
+    if by != "content" {
+        for path in old_files.intersection(&new_files).cloned().collect::<Vec<_>>() {
+            old_files.remove(&path);
+            new_files.remove(&path);
+
+            records.insert(path.clone(), Action::Ignore(escape(&path)));
+        }
+    }
+
+    // Files with the same content are moved to the path in the new directory
+    if by != "path" {
+        let old_sizes = sizes(old, &old_files)?;
+        let new_sizes = sizes(new, &new_files)?;
+
+        let mut targets = checksums(hash, new, &new_sizes, &old_sizes)?;
+
+        for (checksum, sources) in checksums(hash, old, &old_sizes, &new_sizes)? {
+            let targets = match targets.get_mut(&checksum) {
+                Some(targets) => targets,
+                None => continue,
+            };
+
+            // Prefer keeping files which exist in both directories, each target is used only once
+            for source in sources {
+                let target = match targets.iter().position(|target| target == &source) {
+                    Some(i) => targets.remove(i),
+                    None if !targets.is_empty() => targets.remove(0),
+                    None => break,
+                };
+
+                old_files.remove(&source);
+                new_files.remove(&target);
+
+                if source == target {
+                    records.insert(source.clone(), Action::Ignore(escape(&source)));
+                } else {
+                    info!("{} {} {} {}", Paint::cyan("➤").bold(), source.display(), Paint::cyan("→"), target.display());
+                    records.insert(source, Action::Move(target));
+                }
+            }
+        }
+    }
+
+    // All other files do not exist in the new directory
+    for path in old_files.iter() {
+        info!("{} {}", Paint::red("✕").bold(), path.display());
+        records.insert(path.clone(), Action::Delete);
+    }
+
+    for path in new_files.iter() {
+        warn!("{}: no matching file in {}", path.display(), old.display());
+    }
+
+    let changeset = ChangeSet::create(workspace, records);
+    changeset.export()?;
+
+    info!("{} Use mmv edit to review the change set", Paint::green("Initialized."));
+
+    return Ok(());
+}
+
+/// Files grouped by their size and checksum.
+type Checksums = BTreeMap<(u64, Vec<u8>), Vec<PathBuf>>;
+
+fn sizes(root: &Path, files: &BTreeSet<PathBuf>) -> anyhow::Result<BTreeMap<PathBuf, u64>> {
+    return files.iter()
+        .map(|file| Ok((file.clone(), root.join(file).symlink_metadata()?.len())))
+        .collect();
+}
+
+/// Calculates the checksums of `files`, skipping files of a size not existing in `other` as these
+/// can not have the same content.
+fn checksums(hash: HashAlgorithm, root: &Path, files: &BTreeMap<PathBuf, u64>, other: &BTreeMap<PathBuf, u64>) -> anyhow::Result<Checksums> {
+    let other = other.values().collect::<BTreeSet<_>>();
+
+    let mut checksums = Checksums::new();
+    for (file, size) in files.iter().filter(|(_, size)| other.contains(size)) {
+        debug!("hashing {}", root.join(file).display());
+        checksums.entry((*size, hash.checksum(&root.join(file))?)).or_default().push(file.clone());
+    }
+
+    return Ok(checksums);
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("diff-dirs")
+        .about("Initializes a change set transforming a directory into another one")
+        .after_help("The change set is created in OLD. Files in OLD are moved to the file in NEW with the same content \
+            and deleted if there is none. Files only existing in NEW are reported as they can not be created.")
+        .arg(Arg::with_name("old")
+            .value_name("OLD")
+            .help("The directory to transform")
+            .required(true)
+            .index(1))
+        .arg(Arg::with_name("new")
+            .value_name("NEW")
+            .help("The directory with the desired layout")
+            .required(true)
+            .index(2))
+        .arg(Arg::with_name("by")
+            .long("by")
+            .value_name("COMPARISON")
+            .possible_values(&["path", "content", "both"])
+            .help("Match up files by their relative path, their content or the path first and the content for the remaining files")
+            .takes_value(true)
+            .default_value("both"))
+        .arg(Arg::with_name("hash")
+            .long("hash")
+            .value_name("ALGORITHM")
+            .possible_values(HashAlgorithm::NAMES)
+            .help("The algorithm used to compare the file contents, defaults to the configured one or blake3")
+            .takes_value(true))
+        .arg(Arg::with_name("force")
+            .short("f")
+            .long("force")
+            .takes_value(false)
+            .help("Replace an existing change set in OLD"))
+        .args(&ScanFilter::args());
+}
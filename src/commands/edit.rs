@@ -1,26 +1,210 @@
+use std::collections::BTreeSet;
+use std::io::Read;
 use std::path::Path;
+use std::process::Command;
 
-use clap::{App, ArgMatches, SubCommand};
+use anyhow::anyhow;
+use clap::{App, Arg, ArgMatches, SubCommand};
 
-use crate::changeset::Workspace;
+use crate::changeset::{Action, ChangeSetImport, Format, Workspace};
+use crate::config::Config;
 use crate::ProgramError;
 
-pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
-    let workspace = Workspace::open(workspace)
-        .ok_or_else(|| ProgramError::NotInitialized)?;
+/// The built-in editor invocations for the split and the combined format.
+const PRESETS: &[(&str, &str, &str)] = &[
+    ("vim",
+     "vim -O {sources} {targets} -c 'setlocal readonly | setlocal nobuflisted | windo set scb | set cursorline'",
+     "vim {changes}"),
+    ("nvim",
+     "nvim -O {sources} {targets} -c 'setlocal readonly | setlocal nobuflisted | windo set scb | set cursorline'",
+     "nvim {changes}"),
+    ("helix", "hx --vsplit {sources} {targets}", "hx {changes}"),
+    ("kakoune", "kak {sources} {targets}", "kak {changes}"),
+    ("emacs", "emacs {sources} {targets}", "emacs {changes}"),
+    ("nano", "nano {sources} {targets}", "nano {changes}"),
+];
 
-    let changeset = workspace.import()?;
+fn preset(name: &str, format: Format) -> Option<&'static str> {
+    return PRESETS.iter()
+        .find(|(preset, _, _)| *preset == name)
+        .map(|(_, split, combined)| match format {
+            Format::Split => *split,
+            Format::Combined => *combined,
+        });
+}
+
+/// Builds the command to open the editor for the change set files of the workspace.
+///
+/// The command line is taken from the configured command or preset. Otherwise the editor is taken
+/// from `$VISUAL` or `$EDITOR` and falls back to `vim`. Editors of the vim family get a
+/// side-by-side view with synchronized scrolling, all other editors just get the files passed.
+fn editor_command(config: &Config, workspace: &Workspace) -> Result<Command, ProgramError> {
+    let format = workspace.format();
+
+    let command = match format {
+        Format::Split => config.editor.command.as_ref(),
+        Format::Combined => config.editor.combined_command.as_ref(),
+    };
+
+    let command_line = if let Some(command) = command {
+        command.clone()
+    } else if let Some(name) = config.editor.preset.as_ref() {
+        preset(name, format)
+            .ok_or_else(|| anyhow!("Unknown editor preset: {}", name))?
+            .to_string()
+    } else {
+        let editor = std::env::var("VISUAL").ok()
+            .filter(|editor| !editor.trim().is_empty())
+            .or_else(|| std::env::var("EDITOR").ok())
+            .filter(|editor| !editor.trim().is_empty())
+            .unwrap_or_else(|| String::from("vim"));
+
+        // Use the preset if the editor is known and given without arguments
+        let name = Path::new(&editor).file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        match (preset(name, format), format) {
+            (Some(preset), _) if name == editor => preset.to_string(),
+            (_, Format::Split) => format!("{} {{sources}} {{targets}}", editor),
+            (_, Format::Combined) => format!("{} {{changes}}", editor),
+        }
+    };
+
+    let args = shell_words::split(&command_line)
+        .map_err(|err| anyhow!("Invalid editor command '{}': {}", command_line, err))?;
+
+    let placeholders = [
+        ("{sources}", workspace.sources_path()),
+        ("{targets}", workspace.targets_path()),
+        ("{changes}", workspace.changes_path()),
+    ];
+
+    let placeholders = placeholders.iter()
+        .map(|(placeholder, path)| {
+            return path.to_str()
+                .map(|path| (*placeholder, path))
+                .ok_or_else(|| anyhow!("Invalid path: {}", path.display()));
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut args = args.into_iter()
+        .map(|arg| placeholders.iter().fold(arg, |arg, (placeholder, path)| arg.replace(placeholder, path)));
+
+    let program = args.next()
+        .ok_or_else(|| anyhow!("Empty editor command"))?;
 
-    let status = std::process::Command::new("vim")
-        .args(&[
-            "-O",
-            &format!("{}", changeset.workspace().sources_path().display()),
-            &format!("{}", changeset.workspace().targets_path().display()),
-            "-c", "setlocal readonly | setlocal nobuflisted | windo set scb | set cursorline",
-        ])
+    let mut command = Command::new(program);
+    command.args(args);
+
+    return Ok(command);
+}
+
+fn edit(config: &Config, workspace: &Workspace) -> Result<(), ProgramError> {
+    let status = editor_command(config, workspace)?
         .status()
         .map_err(anyhow::Error::from)?;
 
+    if !status.success() {
+        return Err(anyhow!("Editor exited with {}", status).into());
+    }
+
+    return Ok(());
+}
+
+/// Edits only the changed records and the unmapped lines in a temporary workspace and merges the
+/// result back into the change set.
+fn edit_changed(config: &Config, workspace: Workspace) -> Result<(), ProgramError> {
+    let changeset = workspace.import()?;
+    let (workspace, mut records, unmapped_sources, unmapped_targets) = changeset.split();
+
+    let mut changed = records.clone();
+    changed.retain(|source, action| match action {
+        Action::Move(target) => target != source,
+        Action::Delete => true,
+        Action::Ignore(_) => false,
+    });
+    records.retain(|source, _| !changed.contains_key(source));
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("mmv.")
+        .tempdir()
+        .map_err(anyhow::Error::from)?;
+
+    // The sources can not be changed in the editor, as the records are merged back by their source
+    let sources = changed.keys().cloned()
+        .chain(unmapped_sources.iter().map(|(_, source)| source.clone()))
+        .collect::<BTreeSet<_>>();
+
+    let partial = Workspace::at(tempdir.path()).convert(workspace.format())?;
+    ChangeSetImport::create(partial.clone(), changed, unmapped_sources, unmapped_targets).export()?;
+
+    edit(config, &partial)?;
+
+    let (_, changed, unmapped_sources, unmapped_targets) = partial.import()?.split();
+
+    let edited = changed.keys().cloned()
+        .chain(unmapped_sources.iter().map(|(_, source)| source.clone()))
+        .collect::<BTreeSet<_>>();
+    if edited != sources {
+        return Err(anyhow!("Sources must not be changed, the change set is left untouched").into());
+    }
+
+    records.extend(changed);
+
+    ChangeSetImport::create(workspace, records, unmapped_sources, unmapped_targets).export()?;
+
+    return Ok(());
+}
+
+/// Replaces the targets with the content read from stdin.
+///
+/// For workspaces using the combined format, the content replaces the combined file. Lines without
+/// a record ID are numbered by their position, so the n-th line belongs to the record with ID n.
+fn edit_stdin(workspace: &Workspace) -> Result<(), ProgramError> {
+    let mut content = String::new();
+    std::io::stdin().read_to_string(&mut content)
+        .map_err(anyhow::Error::from)?;
+
+    let (path, fields) = match workspace.format() {
+        Format::Split => (workspace.targets_path(), 2),
+        Format::Combined => (workspace.changes_path(), 3),
+    };
+
+    let mut numbered = String::with_capacity(content.len());
+    for (line, record) in content.lines().enumerate() {
+        // Tabs in paths are escaped, so a record carries an ID if it has all fields and the first
+        // one is a number
+        let id = record.split('\t').next()
+            .filter(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()));
+
+        if id.is_none() || record.split('\t').count() != fields {
+            numbered.push_str(&format!("{}\t", line + 1));
+        }
+
+        numbered.push_str(record);
+        numbered.push('\n');
+    }
+
+    std::fs::write(path, numbered)
+        .map_err(anyhow::Error::from)?;
+
+    return Ok(());
+}
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let config = Config::load()?;
+
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    if matches.is_present("stdin") {
+        edit_stdin(&workspace)?;
+    } else if matches.is_present("changed-only") {
+        edit_changed(&config, workspace)?;
+    } else {
+        edit(&config, &workspace)?;
+    }
+
     // TODO: Print brief status afterwards
 
     return Ok(());
@@ -28,5 +212,21 @@ pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
 
 pub fn subcommand() -> App<'static, 'static> {
     return SubCommand::with_name("edit")
-        .about("Opens an editor for the change set");
-}
\ No newline at end of file
+        .about("Opens an editor for the change set")
+        .after_help("The editor is taken from the configuration file or from $VISUAL or $EDITOR and defaults to vim.\n\
+            The configuration can select a preset (vim, nvim, helix, kakoune, emacs, nano) or define the full \
+            command line using {sources} and {targets} as placeholders. Workspaces using the combined format \
+            use combined-command with {changes} as placeholder instead:\n\n    \
+            [editor]\n    \
+            command = \"nvim -d {sources} {targets}\"\n    \
+            combined-command = \"nvim {changes}\"")
+        .arg(Arg::with_name("changed-only")
+            .long("changed-only")
+            .takes_value(false)
+            .help("Only edit records which are moved or deleted and lines which are not mapped"))
+        .arg(Arg::with_name("stdin")
+            .long("stdin")
+            .takes_value(false)
+            .conflicts_with("changed-only")
+            .help("Read the targets file from stdin instead of opening an editor"));
+}
@@ -1,63 +1,2221 @@
-use std::path::Path;
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs::{File, FileTimes, Permissions};
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
 
 use clap::{Arg, ArgMatches, SubCommand, App};
+use globset::{Glob, GlobSetBuilder};
+use indicatif::HumanBytes;
+use rustix::fs::{access, mkfifoat, Access, Mode, CWD};
+use serde_json::json;
 use yansi::Paint;
 
-use crate::changeset::{Action, Workspace};
+use crate::changeset::{Action, ChangeSet, Validation, Workspace};
+use crate::config::{Config, HooksConfig, PreserveAttribute, WorkspaceConfig};
+use crate::escape::escape;
+use crate::expand::expand;
+use crate::hash::HashAlgorithm;
+use crate::hooks;
+use crate::journal::{Journal, JournalEntry};
+use crate::log::{Log, Operation};
+use crate::progress::Progress;
+use crate::git::Repository;
+use crate::routes::{normalize, Router};
+use crate::scan::{is_special, ScanFilter, SymlinkMode};
+use crate::throttle::Throttle;
+use crate::units::parse_size;
+use crate::verbosity::{self, Verbosity};
 use crate::ProgramError;
 
-pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
-    let target = matches.value_of("target").expect("No target");
-    let target = Path::new(target);
+/// Options controlling the execution of a change set.
+struct Options<'a> {
+    /// Resolves the target directory of each record
+    router: &'a Router,
+
+    /// Continue the journal of an aborted execution
+    resume: bool,
+
+    dry_run: bool,
+    keep_going: bool,
+
+    /// Number of groups of records executed concurrently
+    jobs: usize,
+
+    /// Existing targets which must be backed up before being replaced
+    backups: BTreeSet<PathBuf>,
+
+    /// Appended to the name of backed up targets
+    backup_suffix: OsString,
+
+    reflink: ReflinkMode,
+
+    /// Limits the throughput of copies
+    throttle: Option<Throttle>,
+
+    /// The attributes of the source replicated to copies
+    preserve: Preserve,
+
+    /// The algorithm used to compare the checksums of copies before removing the source
+    verify: Option<HashAlgorithm>,
+
+    /// Create hard links instead of copies
+    link: bool,
+
+    /// Leave the copied or linked sources in place
+    keep_sources: bool,
+
+    /// The directory containing the sources
+    source: PathBuf,
+
+    /// Create a symlink at the old location of moved files
+    leave_symlink: bool,
+
+    /// Whether symlinks are recreated at the target or replaced by the files they point to
+    symlinks: SymlinkMode,
+
+    /// The commands run before, during and after the execution
+    hooks: HooksConfig,
+
+    /// The log of all file operations
+    log: Option<Mutex<Log>>,
+}
+
+/// Totals of an execution printed at its end.
+#[derive(Debug, Clone, Default)]
+struct Summary {
+    moved: usize,
+    deleted: usize,
+
+    /// Records not executed because of an existing target
+    skipped: usize,
+
+    failed: usize,
+
+    /// Size of the moved files
+    bytes: u64,
+}
+
+impl Summary {
+    fn print(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let throughput = if seconds > 0.0 { (self.bytes as f64 / seconds) as u64 } else { 0 };
+
+        info!("{} moved, {} deleted, {} skipped, {} failed, {} in {:.1}s ({}/s)",
+              Paint::cyan(self.moved).bold(),
+              Paint::red(self.deleted).bold(),
+              Paint::yellow(self.skipped).bold(),
+              if self.failed > 0 { Paint::red(self.failed).bold() } else { Paint::new(self.failed).bold() },
+              HumanBytes(self.bytes),
+              seconds,
+              HumanBytes(throughput));
+    }
+}
+
+/// Set by the interrupt handler to stop the execution after the record in flight.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Installs a handler for Ctrl-C which lets the execution finish the record in flight, so no
+/// partially copied file is left behind and the journal and change set stay consistent.
+fn handle_interrupts() -> anyhow::Result<()> {
+    ctrlc::set_handler(|| {
+        // A second interrupt aborts immediately
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+
+        eprintln!();
+        eprintln!("{} Finishing the current file, press Ctrl-C again to abort immediately", Paint::yellow("Interrupted."));
+    })?;
+
+    return Ok(());
+}
+
+fn interrupted() -> bool {
+    return INTERRUPTED.load(Ordering::SeqCst);
+}
+
+/// Defines how to handle targets which exist already.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConflictPolicy {
+    Skip,
+    Overwrite,
+    Backup,
+    Ask,
+}
+
+impl std::fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            ConflictPolicy::Skip => write!(f, "skip"),
+            ConflictPolicy::Overwrite => write!(f, "overwrite"),
+            ConflictPolicy::Backup => write!(f, "backup"),
+            ConflictPolicy::Ask => write!(f, "ask"),
+        };
+    }
+}
+
+impl std::str::FromStr for ConflictPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "backup" => Ok(ConflictPolicy::Backup),
+            "ask" => Ok(ConflictPolicy::Ask),
+            _ => Err(anyhow::anyhow!("Invalid conflict policy: {}", s)),
+        };
+    }
+}
+
+/// The attributes of the source replicated to copied files.
+#[derive(Debug, Clone, Copy)]
+struct Preserve {
+    /// Permissions and timestamps
+    metadata: bool,
+
+    /// Extended attributes, excluding ACLs
+    xattr: bool,
+
+    /// POSIX ACLs
+    acl: bool,
+}
+
+/// Defines whether copies of files share their data blocks with the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReflinkMode {
+    /// Reflink if the filesystem supports it, copy otherwise
+    Auto,
+
+    /// Fail if the file can not be reflinked
+    Always,
 
+    /// Always copy the data
+    Never,
+}
+
+impl std::str::FromStr for ReflinkMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "auto" => Ok(ReflinkMode::Auto),
+            "always" => Ok(ReflinkMode::Always),
+            "never" => Ok(ReflinkMode::Never),
+            _ => Err(anyhow::anyhow!("Invalid reflink mode: {}", s)),
+        };
+    }
+}
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
     let workspace = Workspace::open(workspace)
         .ok_or_else(|| ProgramError::NotInitialized)?;
 
-    let changeset = workspace.import()?;
-    let changeset = changeset.clean()
-        .ok_or_else(|| ProgramError::NotClean)?;
+    let changeset = workspace.import()?;
+    let changeset = changeset.clean()
+        .ok_or_else(|| ProgramError::NotClean)?;
+
+    // Without a target directory, the files are moved to the configured one or renamed in place
+    let target = match matches.value_of("target") {
+        Some(target) => PathBuf::from(target),
+        None => WorkspaceConfig::load(changeset.workspace())?.target
+            .unwrap_or_else(|| changeset.path().to_path_buf()),
+    };
+
+    return execute_changeset(changeset, &target, matches, false);
+}
+
+/// Executes the change set to the target directory using the options given by `args`.
+///
+/// If resuming, the journal of the previous execution is continued instead of replaced.
+pub fn execute_changeset(mut changeset: ChangeSet,
+                         target: &Path,
+                         matches: &ArgMatches,
+                         resume: bool) -> Result<(), ProgramError> {
+    // Records are moved to the target directory unless routed elsewhere
+    let mut router = Router::new(target);
+    if let Some(path) = matches.value_of("routes") {
+        router.load(Path::new(path))?;
+    }
+    for rule in matches.values_of("route").into_iter().flatten() {
+        router.add(rule)?;
+    }
+    for rule in WorkspaceConfig::load(changeset.workspace())?.routes.iter() {
+        router.add(rule)?;
+    }
+
+    // Targets are expanded for this execution only. The change set keeps the unexpanded targets of
+    // the records not executed, so it stays usable on other machines
+    let mut unexpanded = BTreeMap::new();
+    if matches.is_present("expand") {
+        for (record, action) in changeset.records_mut().iter_mut() {
+            if let Action::Move(path) = action {
+                let expanded = expand(path)?;
+                if expanded != *path {
+                    unexpanded.insert(record.clone(), (std::mem::replace(path, expanded.clone()), expanded));
+                }
+            }
+        }
+    }
+
+    if matches.is_present("auto-rename") {
+        auto_rename(&mut changeset, &router, matches.is_present("json"))?;
+    }
+
+    // Refuse to execute if the change set is invalid. Existing targets are handled below
+    let problems = changeset.validate(&Validation {
+        router: Some(&router),
+        allow_outside: matches.is_present("allow-outside"),
+        allow_absolute: matches.is_present("allow-absolute") || matches.is_present("expand"),
+        portable_names: matches.is_present("portable-names"),
+        ..Validation::default()
+    })?;
+    if !problems.is_empty() {
+        for problem in problems.iter() {
+            problem.print();
+        }
+
+        return Err(ProgramError::Invalid);
+    }
+
+    let dry_run = matches.is_present("dry-run");
+    let json = matches.is_present("json");
+
+    let on_conflict = matches.value_of("on-conflict").expect("No conflict policy")
+        .parse::<ConflictPolicy>()?;
+
+    // Overwritten targets are backed up instead if requested
+    let backup = matches.is_present("backup");
+
+    let link = matches.is_present("link");
+    let keep_sources = (link && !matches.is_present("remove-sources")) || matches.is_present("copy");
+
+    // Copying or linking without removing the sources must not replace any source, as other
+    // records still refer to them and the journal could not restore them
+    if keep_sources {
+        let sources = changeset.records().keys()
+            .map(|record| std::path::absolute(changeset.path().join(record)))
+            .collect::<Result<BTreeSet<_>, _>>()
+            .map_err(anyhow::Error::from)?;
+
+        let mut invalid = false;
+        for (record, action) in changeset.records().iter() {
+            if let Action::Move(path) = action {
+                let source = std::path::absolute(changeset.path().join(record)).map_err(anyhow::Error::from)?;
+                let target = std::path::absolute(router.resolve(record, path)).map_err(anyhow::Error::from)?;
+
+                if target != source && sources.contains(&target) {
+                    eprintln!("{} {}: target replaces a source which is kept", Paint::red("✗").bold(), target.display());
+                    invalid = true;
+                }
+            }
+        }
+
+        if invalid {
+            return Err(ProgramError::Invalid);
+        }
+    }
+
+    // Select the records to execute in this run. Records not selected are excluded from the plan
+    // and stay in the change set
+    let mut plan = changeset.clone();
+
+    // Records excluded from this run along with the reason
+    let mut excluded = BTreeMap::new();
+
+    if let Some(patterns) = matches.values_of("only") {
+        let mut set = GlobSetBuilder::new();
+        for pattern in patterns {
+            set.add(Glob::new(pattern).map_err(anyhow::Error::from)?);
+        }
+
+        let set = set.build().map_err(anyhow::Error::from)?;
+        plan.records_mut().retain(|record, action| {
+            let selected = set.is_match(record);
+            if !selected && !matches!(action, Action::Ignore(_)) {
+                verbose!("skipping {}: not selected by --only", record.display());
+                excluded.insert(record.clone(), "not selected by --only");
+            }
+            return selected;
+        });
+    }
+
+    if matches.is_present("moves-only") {
+        plan.records_mut().retain(|record, action| {
+            if matches!(action, Action::Delete) {
+                verbose!("skipping {}: not a move", record.display());
+                excluded.insert(record.clone(), "not a move");
+                return false;
+            }
+            return true;
+        });
+    }
+
+    if matches.is_present("deletes-only") {
+        plan.records_mut().retain(|record, action| {
+            if matches!(action, Action::Move(_)) {
+                verbose!("skipping {}: not a deletion", record.display());
+                excluded.insert(record.clone(), "not a deletion");
+                return false;
+            }
+            return true;
+        });
+    }
+
+    if matches.is_present("interactive") {
+        for record in select_interactive(&mut plan, &router)? {
+            excluded.insert(record, "declined");
+        }
+    }
+
+    let mut summary = Summary::default();
+    exclude_blocked(&changeset, &mut plan, &router, &mut excluded, &mut summary)?;
+
+    // Decide how to handle targets which exist already before touching any file. Skipped records
+    // are excluded from the plan and stay in the change set
+    let selected = plan.clone();
+    let mut backups = BTreeSet::new();
+    let mut conflicts = BTreeMap::new();
+    let vacated = if keep_sources { BTreeSet::new() } else { vacated_sources(&selected, &router)? };
+    for (record, action) in selected.records().iter() {
+        if let Action::Move(path) = action {
+            let target = router.resolve(record, path);
+            if target.symlink_metadata().is_err() {
+                continue;
+            }
+
+            // Existing files moved away or deleted by the change set are no conflicts, just like
+            // files moved onto themselves
+            let absolute = std::path::absolute(&target).map_err(anyhow::Error::from)?;
+            if vacated.contains(&absolute) || absolute == std::path::absolute(selected.path().join(record)).map_err(anyhow::Error::from)? {
+                continue;
+            }
+
+            // Changing only the case of a name on a case-insensitive file system finds the source
+            // itself at the target
+            if is_case_rename(&selected.path().join(record), &target) {
+                continue;
+            }
+
+            let policy = match on_conflict {
+                ConflictPolicy::Ask if !dry_run => ask_conflict(&target)?,
+                policy => policy,
+            };
+
+            let policy = match policy {
+                ConflictPolicy::Overwrite if backup => ConflictPolicy::Backup,
+                policy => policy,
+            };
+
+            let message = match policy {
+                ConflictPolicy::Skip => "exists, skipping",
+                ConflictPolicy::Overwrite => "exists, overwriting",
+                ConflictPolicy::Backup => "exists, backing up",
+                ConflictPolicy::Ask => "exists",
+            };
+
+            // The JSON output must not be mixed with messages
+            if !json {
+                info!("{} {} {}", Paint::yellow("⚠").bold(), target.display(), Paint::yellow(message));
+            }
+
+            match policy {
+                ConflictPolicy::Skip => {
+                    plan.records_mut().remove(record);
+                    excluded.insert(record.clone(), "skipped");
+                    summary.skipped += 1;
+                }
+                ConflictPolicy::Backup => {
+                    backups.insert(target);
+                }
+                ConflictPolicy::Overwrite | ConflictPolicy::Ask => {}
+            }
+
+            conflicts.insert(record.clone(), policy);
+        }
+    }
+
+    // Skipped records keep their sources, which other records can not be moved onto
+    exclude_blocked(&changeset, &mut plan, &router, &mut excluded, &mut summary)?;
+
+    // Files kept in place by copying or linking are not deleted either
+    if keep_sources {
+        plan.records_mut().retain(|_, action| !matches!(action, Action::Delete));
+    }
+
+    // Moves across file systems copy the files, which is much slower than renaming them
+    let crossing = plan.records().iter()
+        .filter_map(|(record, action)| match action {
+            Action::Move(path) => Some((record, router.resolve(record, path))),
+            Action::Delete | Action::Ignore(_) => None,
+        })
+        .filter(|(record, target)| crosses_device(&changeset.path().join(record), target))
+        .map(|(record, _)| record.clone())
+        .collect::<BTreeSet<_>>();
+
+    if matches.is_present("no-cross-device") && !crossing.is_empty() {
+        for record in crossing.iter() {
+            eprintln!("{} {}: target is on another device", Paint::red("✗").bold(), record.display());
+        }
+
+        return Err(anyhow::anyhow!("Refusing to move files across devices").into());
+    }
+
+    if json {
+        let plan = plan_json(&changeset, &plan, &router, &conflicts, &crossing)?;
+        println!("{}", serde_json::to_string_pretty(&plan).map_err(anyhow::Error::from)?);
+        return Ok(());
+    }
+
+    if !crossing.is_empty() {
+        let moves = plan.records().values()
+            .filter(|action| matches!(action, Action::Move(_)))
+            .count();
+        info!("{} {} of {} moves cross devices and are copied", Paint::yellow("⚠").bold(), crossing.len(), moves);
+    }
+
+    let jobs = matches.value_of("jobs").unwrap_or("1")
+        .parse::<usize>()
+        .ok()
+        .filter(|jobs| *jobs > 0)
+        .ok_or_else(|| anyhow::anyhow!("Invalid number of jobs"))?;
+
+    // The configuration only provides the defaults of options not given on the command line, so
+    // it is read on first use
+    let config = OnceCell::new();
+
+    let verify = matches.is_present("verify")
+        || (!matches.is_present("no-verify") && configured(&config)?.execute.verify);
+
+    // The algorithm given on the command line takes precedence over the configured one
+    let verify = match (verify, matches.value_of("hash")) {
+        (false, _) => None,
+        (true, Some(hash)) => Some(hash.parse::<HashAlgorithm>()?),
+        (true, None) => Some(configured(&config)?.checksum.algorithm.unwrap_or_default()),
+    };
+
+    let preserve = |attribute: PreserveAttribute, name: &str| -> anyhow::Result<bool> {
+        return Ok(matches.values_of("preserve").into_iter().flatten().any(|value| value == name)
+            || configured(&config)?.execute.preserve.contains(&attribute));
+    };
+
+    let options = Options {
+        router: &router,
+        resume,
+        dry_run,
+        keep_going: matches.is_present("keep-going") || configured(&config)?.execute.keep_going,
+        jobs,
+        backups,
+        backup_suffix: OsString::from(matches.value_of("backup").unwrap_or("~")),
+        reflink: matches.value_of("reflink").expect("No reflink mode")
+            .parse::<ReflinkMode>()?,
+        throttle: matches.value_of("bwlimit")
+            .map(parse_size)
+            .transpose()?
+            .filter(|rate| *rate > 0)
+            .map(Throttle::new),
+        preserve: Preserve {
+            metadata: !matches.is_present("no-preserve"),
+            xattr: preserve(PreserveAttribute::Xattr, "xattr")?,
+            acl: preserve(PreserveAttribute::Acl, "acl")?,
+        },
+        verify,
+        link,
+        keep_sources,
+        source: changeset.path().to_path_buf(),
+        leave_symlink: matches.is_present("leave-symlink"),
+        symlinks: ScanFilter::load(changeset.workspace())?.symlinks(),
+        hooks: if dry_run || matches.is_present("no-hooks") { HooksConfig::default() } else { configured(&config)?.hooks.clone() },
+        log: match matches.value_of_os("log-file").map(PathBuf::from) {
+            _ if dry_run => None,
+            Some(path) => Some(Mutex::new(Log::open(&path)?)),
+            None => match configured(&config)?.execute.log_file.as_ref() {
+                Some(path) => Some(Mutex::new(Log::open(path)?)),
+                None => None,
+            },
+        },
+    };
+
+    if !matches.is_present("no-permission-check") {
+        check_permissions(&plan, &options)?;
+    }
+
+    if !matches.is_present("no-space-check") {
+        check_space(&plan, &options, matches.is_present("atomic"))?;
+    }
+
+    if !options.dry_run {
+        handle_interrupts()?;
+    }
+
+    // The repository is looked up before touching any file, so a missing one does not leave the
+    // index behind
+    let repository = if (matches.is_present("git") || matches.is_present("commit")) && !options.dry_run {
+        Some(Repository::discover(changeset.path())?
+            .ok_or_else(|| anyhow::anyhow!("Not a git repository: {}", changeset.path().display()))?)
+    } else {
+        None
+    };
+
+    // The hooks get the plan as JSON along with the workspace and target directory
+    let hook_input = if options.hooks.pre_execute.is_some() || options.hooks.post_execute.is_some() {
+        serde_json::to_vec_pretty(&plan_json(&changeset, &plan, &router, &conflicts, &crossing)?)
+            .map_err(anyhow::Error::from)?
+    } else {
+        Vec::new()
+    };
+    let hook_env = [
+        ("MMV_WORKSPACE", std::path::absolute(changeset.path()).map_err(anyhow::Error::from)?.into_os_string()),
+        ("MMV_TARGET_DIR", std::path::absolute(router.default()).map_err(anyhow::Error::from)?.into_os_string()),
+    ];
+
+    if let Some(hook) = options.hooks.pre_execute.as_ref() {
+        hooks::run("pre-execute", hook, hook_env.clone(), &hook_input)?;
+    }
+
+    // Sources of all records which have been applied successfully
+    let mut completed = Vec::new();
+
+    let started = Instant::now();
+    if let Some(log) = options.log.as_ref() {
+        let records = plan.records().values()
+            .filter(|action| !matches!(action, Action::Ignore(_)))
+            .count();
+        log.lock().expect("Log poisoned")
+            .start(&std::path::absolute(changeset.path()).map_err(anyhow::Error::from)?,
+                   &std::path::absolute(router.default()).map_err(anyhow::Error::from)?,
+                   records)?;
+    }
+
+    let result = if !options.dry_run && matches.is_present("atomic") {
+        execute_atomic(&plan, &options, &mut completed, &mut summary)
+    } else {
+        execute(&plan, &options, &mut completed, &mut summary)
+    };
+
+    if let Some(log) = options.log.as_ref() {
+        let status = match result {
+            Ok(()) => "success",
+            Err(ProgramError::Interrupted) => "interrupted",
+            Err(_) => "failure",
+        };
+        if let Err(err) = log.lock().expect("Log poisoned").finish(status, started.elapsed()) {
+            warn!("{}", err);
+        }
+    }
+
+    if !options.dry_run {
+        summary.print(started.elapsed());
+    }
+
+    // Remove the completed records from the change set, even if the execution failed, as their
+    // sources are gone and executing them again would fail. Copied or linked sources stay in place
+    // and keep their records
+    if !completed.is_empty() && !options.keep_sources {
+        for source in completed.iter() {
+            changeset.records_mut().remove(source);
+        }
+
+        for (record, (path, expanded)) in unexpanded {
+            if let Some(Action::Move(target)) = changeset.records_mut().get_mut(&record) {
+                if *target == expanded {
+                    *target = path;
+                }
+            }
+        }
+
+        changeset.export()?;
+    }
+
+    // Carry the index entries of the completed records over to their targets
+    if let Some(repository) = repository {
+        let mut moves = Vec::new();
+        let mut deletes = Vec::new();
+        let mut summary = Vec::new();
+        let mut moved = 0;
+        for record in completed.iter() {
+            let source = changeset.path().join(record);
+            match &plan.records()[record] {
+                Action::Move(path) => {
+                    let target = router.resolve(record, path);
+                    if std::path::absolute(&source).ok() != std::path::absolute(&target).ok() {
+                        summary.push(format!("{} -> {}", escape(record), escape(path)));
+                        moved += 1;
+                    }
+                    moves.push((source, target));
+                }
+                Action::Delete => {
+                    summary.push(format!("delete {}", escape(record)));
+                    deletes.push(source);
+                }
+                Action::Ignore(_) => {}
+            }
+        }
+
+        let changed = repository.update_index(&moves, &deletes)?;
+
+        if let Some(subject) = matches.is_present("commit").then(|| matches.value_of("commit")) {
+            if !changed.is_empty() {
+                let subject = subject.map_or_else(|| format!("Move {} and delete {} files", moved, deletes.len()), str::to_string);
+                repository.commit(&format!("{}\n\n{}\n", subject, summary.join("\n")), &changed)?;
+            }
+        }
+    }
+
+    if !options.dry_run && !matches.is_present("keep-empty-dirs") {
+        prune_empty_dirs(changeset.path(), &completed);
+    }
+
+    // A failing hook does not fail the completed execution
+    if let Some(hook) = options.hooks.post_execute.as_ref() {
+        let status = OsString::from(if result.is_ok() { "success" } else { "failure" });
+        let env = hook_env.iter().cloned().chain(Some(("MMV_STATUS", status)));
+
+        if let Err(err) = hooks::run("post-execute", hook, env, &hook_input) {
+            warn!("{}", err);
+        }
+    }
+
+    return result;
+}
+
+/// Renames targets colliding with the target of a previous record or with an existing file by
+/// appending an incrementing number to the name, i.e. `file (1).jpg`.
+fn auto_rename(changeset: &mut ChangeSet, router: &Router, quiet: bool) -> anyhow::Result<()> {
+    let vacated = vacated_sources(changeset, router)?.iter()
+        .map(|source| normalize(source))
+        .collect::<BTreeSet<_>>();
+    let root = changeset.path().to_path_buf();
+
+    // Targets are compared by their resolved absolute path, as different targets may end up at the
+    // same location
+    let resolved = |source: &Path, path: &Path| -> anyhow::Result<PathBuf> {
+        return router.locate(source, path);
+    };
+
+    let taken = |source: &Path, path: &Path| -> bool {
+        return path.symlink_metadata().is_ok() && !vacated.contains(path) && !is_case_rename(&root.join(source), path);
+    };
+
+    let mut assigned = BTreeSet::new();
+    for (source, action) in changeset.records_mut().iter_mut() {
+        let path = match action {
+            Action::Move(path) => path,
+            Action::Delete | Action::Ignore(_) => continue,
+        };
+
+        // A file moved onto itself keeps its name
+        let target = resolved(source, path)?;
+        let own = target == normalize(&std::path::absolute(root.join(source))?);
+
+        if !own && (assigned.contains(&target) || taken(source, &target)) {
+            let mut n = 1;
+            let renamed = loop {
+                let candidate = numbered(path, n);
+                let target = resolved(source, &candidate)?;
+                if !assigned.contains(&target) && !taken(source, &target) {
+                    break candidate;
+                }
+
+                n += 1;
+            };
+
+            if !quiet {
+                info!("{} {} {} {}", Paint::yellow("⚠").bold(), path.display(), Paint::yellow("collides, renaming to"), renamed.display());
+            }
+
+            *path = renamed;
+        }
+
+        assigned.insert(resolved(source, path)?);
+    }
+
+    return Ok(());
+}
+
+/// Checks if the target is the source itself, only differing in the case of its name.
+fn is_case_rename(source: &Path, target: &Path) -> bool {
+    let (source_metadata, target_metadata) = match (source.symlink_metadata(), target.symlink_metadata()) {
+        (Ok(source), Ok(target)) => (source, target),
+        _ => return false,
+    };
+
+    if (source_metadata.dev(), source_metadata.ino()) != (target_metadata.dev(), target_metadata.ino()) {
+        return false;
+    }
+
+    // Hard links share the inode as well, but have names of their own
+    return match (std::path::absolute(source), std::path::absolute(target)) {
+        (Ok(source), Ok(target)) => source.to_string_lossy().to_lowercase() == target.to_string_lossy().to_lowercase(),
+        _ => false,
+    };
+}
+
+/// Returns the configuration, which is read on first use.
+fn configured(config: &OnceCell<Config>) -> anyhow::Result<&Config> {
+    if config.get().is_none() {
+        let _ = config.set(Config::load()?);
+    }
+
+    return Ok(config.get().expect("No config"));
+}
+
+/// Appends a number to the name of a file, keeping the extension.
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_owned();
+    name.push(format!(" ({})", n));
+
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+
+    return path.with_file_name(name);
+}
+
+/// Returns the absolute paths of all files moved away or deleted by the change set.
+///
+/// Files moved onto themselves stay where they are and are not vacated.
+fn vacated_sources(changeset: &ChangeSet, router: &Router) -> anyhow::Result<BTreeSet<PathBuf>> {
+    let mut vacated = BTreeSet::new();
+    for (record, action) in changeset.records().iter() {
+        let source = std::path::absolute(changeset.path().join(record))?;
+        match action {
+            Action::Move(path) if std::path::absolute(router.resolve(record, path))? == source => {}
+            Action::Move(_) | Action::Delete => {
+                vacated.insert(source);
+            }
+            Action::Ignore(_) => {}
+        }
+    }
+
+    return Ok(vacated);
+}
+
+/// Excludes the records from the plan which would replace the source of an excluded record.
+///
+/// The sources of excluded records stay in place, so moving another file there would destroy
+/// them. Excluding a record can block others in turn, so this repeats until no record is blocked.
+fn exclude_blocked(changeset: &ChangeSet,
+                   plan: &mut ChangeSet,
+                   router: &Router,
+                   excluded: &mut BTreeMap<PathBuf, &'static str>,
+                   summary: &mut Summary) -> anyhow::Result<()> {
+    loop {
+        let mut kept = BTreeMap::new();
+        for (record, reason) in excluded.iter() {
+            if let Some(Action::Move(_) | Action::Delete) = changeset.records().get(record) {
+                kept.insert(std::path::absolute(changeset.path().join(record))?, (record.clone(), *reason));
+            }
+        }
+
+        let mut blocked = Vec::new();
+        for (record, action) in plan.records().iter() {
+            if let Action::Move(path) = action {
+                let target = std::path::absolute(router.resolve(record, path))?;
+                if let Some((other, reason)) = kept.get(&target) {
+                    warn!("{}: target is kept by {} ({}), skipping", record.display(), other.display(), reason);
+                    blocked.push(record.clone());
+                }
+            }
+        }
+
+        if blocked.is_empty() {
+            return Ok(());
+        }
+
+        for record in blocked {
+            plan.records_mut().remove(&record);
+            excluded.insert(record, "blocked");
+            summary.skipped += 1;
+        }
+    }
+}
+
+/// Builds the structured representation of the planned operations used for JSON output.
+///
+/// Records skipped due to conflicts are listed separately as they stay in the change set.
+fn plan_json(changeset: &ChangeSet,
+             plan: &ChangeSet,
+             router: &Router,
+             conflicts: &BTreeMap<PathBuf, ConflictPolicy>,
+             crossing: &BTreeSet<PathBuf>) -> anyhow::Result<serde_json::Value> {
+    let mut operations = Vec::new();
+    for (record, action) in plan.records().iter() {
+        let source = escape(&std::path::absolute(changeset.path().join(record))?);
+
+        match action {
+            Action::Move(path) => operations.push(json!({
+                "action": "move",
+                "source": source,
+                "target": escape(&std::path::absolute(router.resolve(record, path))?),
+                "conflict": conflicts.get(record).map(ConflictPolicy::to_string),
+                "cross_device": crossing.contains(record),
+            })),
+            Action::Delete => operations.push(json!({
+                "action": "delete",
+                "source": source,
+            })),
+            Action::Ignore(_) => {}
+        }
+    }
+
+    let skipped = changeset.records().keys()
+        .filter(|record| !plan.records().contains_key(*record))
+        .map(|record| escape(record))
+        .collect::<Vec<_>>();
+
+    return Ok(json!({
+        "workspace": escape(&std::path::absolute(changeset.path())?),
+        "target": escape(&std::path::absolute(router.default())?),
+        "operations": operations,
+        "skipped": skipped,
+    }));
+}
+
+/// Removes the directories which became empty by moving or deleting the completed records.
+///
+/// Directories are removed bottom-up until a non-empty directory or the workspace root is reached.
+fn prune_empty_dirs(root: &Path, completed: &[PathBuf]) {
+    let mut dirs = completed.iter()
+        .filter_map(|record| record.parent())
+        .filter(|dir| dir != &Path::new(""))
+        .collect::<BTreeSet<_>>();
+
+    while let Some(dir) = dirs.pop_last() {
+        // Removing fails for non-empty directories, which is fine
+        if std::fs::remove_dir(root.join(dir)).is_err() {
+            continue;
+        }
+
+        if let Some(parent) = dir.parent() {
+            if parent != Path::new("") {
+                dirs.insert(parent);
+            }
+        }
+    }
+}
+
+/// A single file operation of the execution.
+enum Step<'a> {
+    /// Moves a file of a record. Files in a cycle are moved in two steps using a temporary file,
+    /// only the last step completes the record.
+    Move { record: &'a Path, source: PathBuf, target: PathBuf, last: bool },
+
+    /// Deletes the file of a record
+    Delete { record: &'a Path, source: PathBuf },
+
+    /// The record moves the file onto itself and is complete without touching it
+    Keep { record: &'a Path },
+}
+
+/// Orders the actions of the change set so no file is replaced before it has been moved away.
+///
+/// A record moving a file to the source of another record is executed after that record. Records
+/// forming a cycle, like swapping two files, are resolved by moving one of the files to a temporary
+/// name first.
+///
+/// The steps are returned in groups which do not depend on each other and can be executed
+/// concurrently, while the steps of a group must be executed in order.
+fn schedule<'a>(changeset: &'a ChangeSet, router: &Router) -> anyhow::Result<Vec<Vec<Step<'a>>>> {
+    let mut records = Vec::new();
+    for (record, action) in changeset.records().iter() {
+        let source = changeset.path().join(record);
+        let target = match action {
+            Action::Move(path) => Some(router.resolve(record, path)),
+            Action::Delete => None,
+            Action::Ignore(_) => continue,
+        };
+
+        records.push((record.as_path(), source, target));
+    }
+
+    // Each source is vacated by exactly one record and each target is unique, so the dependencies
+    // form disjoint chains and cycles
+    let mut vacated = BTreeMap::new();
+    for (i, (_, source, _)) in records.iter().enumerate() {
+        vacated.insert(std::path::absolute(source)?, i);
+    }
+
+    let mut dependencies = Vec::with_capacity(records.len());
+    let mut keep = Vec::with_capacity(records.len());
+    for (i, (_, _, target)) in records.iter().enumerate() {
+        let dependency = match target {
+            Some(target) => vacated.get(&std::path::absolute(target)?).copied(),
+            None => None,
+        };
+
+        keep.push(dependency == Some(i));
+        dependencies.push(dependency.filter(|dependency| *dependency != i));
+    }
+
+    let step = |i: usize| -> Step<'a> {
+        let (record, source, target) = &records[i];
+        return match target {
+            Some(_) if keep[i] => Step::Keep { record },
+            Some(target) => Step::Move { record, source: source.clone(), target: target.clone(), last: true },
+            None => Step::Delete { record, source: source.clone() },
+        };
+    };
+
+    // The group each record has been scheduled in
+    let mut groups: Vec<Vec<Step<'a>>> = Vec::new();
+    let mut scheduled: Vec<Option<usize>> = vec![None; records.len()];
+    for start in 0..records.len() {
+        if scheduled[start].is_some() {
+            continue;
+        }
+
+        // Follow the records which must be executed before. If one of them has been scheduled
+        // already, it was the first record of its group, which must be continued
+        let mut chain = vec![start];
+        let mut cycle = false;
+        let mut group = None;
+
+        let mut next = dependencies[start];
+        while let Some(i) = next {
+            if i == start {
+                cycle = true;
+                break;
+            }
+
+            if scheduled[i].is_some() {
+                group = scheduled[i];
+                break;
+            }
+
+            chain.push(i);
+            next = dependencies[i];
+        }
+
+        let group = group.unwrap_or_else(|| {
+            groups.push(Vec::new());
+            return groups.len() - 1;
+        });
+
+        for i in chain.iter() {
+            scheduled[*i] = Some(group);
+        }
+
+        let steps = &mut groups[group];
+
+        if cycle {
+            let (record, source, target) = &records[start];
+            let target = target.clone().expect("Cycle without move");
+
+            let mut temporary = OsString::from(".mmv.cycle.");
+            temporary.push(std::process::id().to_string());
+            temporary.push(".");
+            temporary.push(source.file_name().unwrap_or_default());
+            let temporary = source.with_file_name(temporary);
+
+            steps.push(Step::Move { record, source: source.clone(), target: temporary.clone(), last: false });
+            steps.extend(chain[1..].iter().rev().map(|i| step(*i)));
+            steps.push(Step::Move { record, source: temporary, target, last: true });
+        } else {
+            steps.extend(chain.iter().rev().map(|i| step(*i)));
+        }
+    }
+
+    return Ok(groups);
+}
+
+/// The result of a single step executed by a worker.
+struct Outcome<'a> {
+    record: &'a Path,
+    source: PathBuf,
+
+    /// The step completes the record
+    last: bool,
+
+    /// The line describing the step, missing for steps without any file operation
+    line: Option<String>,
+
+    /// Size of the moved file counted as progress
+    bytes: u64,
+
+    /// The target of a moved file
+    target: Option<PathBuf>,
+
+    /// Time spent on the step
+    duration: Duration,
+
+    result: anyhow::Result<Vec<JournalEntry>>,
+}
+
+/// Formats the line printed for a move, marking moves which copy the file to another device.
+fn move_line(source: &Path, target: &Path) -> String {
+    if crosses_device(source, target) {
+        return format!("{} {} {}", Paint::cyan("➤").bold(), target.display(), Paint::new("(across devices)").dimmed());
+    }
+
+    return format!("{} {}", Paint::cyan("➤").bold(), target.display());
+}
+
+/// Executes a single step of the schedule.
+fn execute_step<'a>(step: Step<'a>, options: &Options) -> Outcome<'a> {
+    let started = Instant::now();
+
+    return match step {
+        Step::Move { record, source, target, last } => {
+            let line = move_line(&source, &target);
+
+            // Moving the file to a temporary name in a cycle does not count as progress
+            let bytes = if last { file_size(&source) } else { 0 };
+
+            let result = backup_file(&target, options)
+                .and_then(|backup| {
+                    move_file(&source, &target, options)?;
+
+                    let entry = match (options.keep_sources, options.link) {
+                        (true, true) => JournalEntry::linked(&source, &target)?,
+                        (true, false) => JournalEntry::copied(&source, &target)?,
+                        (false, _) => JournalEntry::moved(&source, &target)?,
+                    };
+
+                    return Ok(backup.into_iter().chain(Some(entry)).collect::<Vec<_>>());
+                });
+
+            Outcome { record, source, last, line: Some(line), bytes, target: Some(target), duration: started.elapsed(), result }
+        }
+
+        Step::Delete { record, source } => {
+            let line = format!("{} {}", Paint::red("✕").bold(), source.display());
+
+            let result = delete_file(&source)
+                .and_then(|()| JournalEntry::deleted(&source))
+                .map(|entry| vec![entry]);
+
+            Outcome { record, source, last: true, line: Some(line), bytes: 0, target: None, duration: started.elapsed(), result }
+        }
+
+        Step::Keep { record } => {
+            Outcome { record, source: PathBuf::new(), last: true, line: None, bytes: 0, target: None, duration: started.elapsed(), result: Ok(Vec::new()) }
+        }
+    };
+}
+
+/// Runs the post-record hook for a completed record with the action, source and target in the
+/// environment.
+fn record_hook(changeset: &ChangeSet, record: &Path, options: &Options) -> anyhow::Result<()> {
+    let hook = match options.hooks.post_record.as_ref() {
+        Some(hook) => hook,
+        None => return Ok(()),
+    };
+
+    let mut env = vec![
+        ("MMV_SOURCE", std::path::absolute(changeset.path().join(record))?.into_os_string()),
+    ];
+
+    match &changeset.records()[record] {
+        Action::Move(path) => {
+            env.push(("MMV_ACTION", OsString::from("move")));
+            env.push(("MMV_TARGET", std::path::absolute(options.router.resolve(record, path))?.into_os_string()));
+        }
+        Action::Delete => env.push(("MMV_ACTION", OsString::from("delete"))),
+        Action::Ignore(_) => return Ok(()),
+    }
+
+    return hooks::run("post-record", hook, env, &[]);
+}
+
+fn execute(changeset: &ChangeSet, options: &Options, completed: &mut Vec<PathBuf>, summary: &mut Summary) -> Result<(), ProgramError> {
+    let groups = schedule(changeset, options.router)?;
+
+    if options.dry_run {
+        for step in groups.iter().flatten() {
+            match step {
+                Step::Move { source, target, .. } => println!("{}", move_line(source, target)),
+                Step::Delete { source, .. } => println!("{} {}", Paint::red("✕").bold(), source.display()),
+                Step::Keep { .. } => {}
+            }
+        }
+
+        return Ok(());
+    }
+
+    let mut journal = open_journal(changeset, options)?;
+
+    // Records without any file operation are not counted
+    let mut records = 0;
+    let mut bytes = 0;
+    for step in groups.iter().flatten() {
+        match step {
+            Step::Move { record, last: true, .. } => {
+                records += 1;
+                bytes += file_size(&changeset.path().join(record));
+            }
+            Step::Delete { .. } => records += 1,
+            Step::Move { .. } | Step::Keep { .. } => {}
+        }
+    }
+
+    let mut progress = Progress::new(records, bytes);
+
+    let mut failures = Vec::new();
+    let mut error = None;
+
+    // Set to keep the workers from starting further steps after a failure
+    let stopped = AtomicBool::new(false);
+
+    // The groups are independent of each other and are picked up by the next idle worker. The
+    // journal and the output are handled by this thread only, so the lines are not interleaved
+    let groups = Mutex::new(groups.into_iter());
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..options.jobs {
+            let sender = sender.clone();
+            let groups = &groups;
+            let stopped = &stopped;
+
+            scope.spawn(move || {
+                while let Some(group) = groups.lock().expect("Worker panicked").next() {
+                    for step in group {
+                        if interrupted() || stopped.load(Ordering::SeqCst) {
+                            return;
+                        }
+
+                        let outcome = execute_step(step, options);
+                        let failed = outcome.result.is_err();
+
+                        if sender.send(outcome).is_err() {
+                            return;
+                        }
+
+                        // The remaining steps of the group depend on the failed one
+                        if failed {
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+
+        drop(sender);
+
+        for outcome in receiver {
+            let result = outcome.result.and_then(|entries| {
+                for entry in entries {
+                    journal.record(entry)?;
+                }
+
+                return Ok(());
+            });
+
+            if let (Some(log), Some(_)) = (options.log.as_ref(), outcome.line.as_ref()) {
+                let action = if outcome.target.is_some() { "move" } else { "delete" };
+                let operation = Operation {
+                    record: outcome.record,
+                    action,
+                    source: &outcome.source,
+                    target: outcome.target.as_deref(),
+                    bytes: outcome.bytes,
+                    duration: outcome.duration,
+                };
+
+                if let Err(err) = log.lock().expect("Log poisoned").record(operation, result.as_ref().err()) {
+                    progress.warning(&err.to_string());
+                }
+            }
+
+            match result {
+                Ok(()) => {
+                    if outcome.last {
+                        completed.push(outcome.record.to_path_buf());
+                    }
+
+                    if let Some(line) = outcome.line {
+                        progress.succeeded(&line, outcome.bytes, outcome.last);
+
+                        summary.bytes += outcome.bytes;
+                        if outcome.last {
+                            match outcome.target {
+                                Some(_) => summary.moved += 1,
+                                None => summary.deleted += 1,
+                            }
+
+                            if let Err(err) = record_hook(changeset, outcome.record, options) {
+                                progress.warning(&err.to_string());
+                            }
+                        }
+                    }
+                }
+
+                Err(err) => {
+                    if let Some(line) = outcome.line {
+                        progress.failed(&line);
+                        summary.failed += 1;
+                    }
+
+                    if !options.keep_going {
+                        stopped.store(true, Ordering::SeqCst);
+                        error.get_or_insert(err);
+                        continue;
+                    }
+
+                    progress.message(&format!("  {}", Paint::red(&err)));
+                    failures.push((outcome.source, err));
+                }
+            }
+        }
+    });
+
+    progress.finish();
+
+    // The symlinks are left after all moves are done, as a later move in the same run may take
+    // over the old location of a file
+    if options.leave_symlink {
+        leave_symlinks(changeset, options, completed, &mut journal);
+    }
+
+    if let Some(err) = error {
+        return Err(err.into());
+    }
+
+    if interrupted() {
+        return Err(ProgramError::Interrupted);
+    }
+
+    if !failures.is_empty() {
+        eprintln!("{} {} of {} records", Paint::red("Failed").bold(), failures.len(), changeset.records().len());
+        for (source, err) in failures.iter() {
+            eprintln!("  {} {}", source.display(), Paint::red(err));
+        }
+
+        return Err(anyhow::anyhow!("Failed to execute {} records", failures.len()).into());
+    }
+
+    return Ok(());
+}
+
+/// Executes the change set in a transactional manner.
+///
+/// All moved files are copied into a staging directory inside the target first. Only after all
+/// files are staged and verified by their checksum, the staged files are renamed to their final
+/// locations and the sources are removed. If anything fails while staging, the staging directory
+/// is dropped and the source tree is left untouched. If committing a record fails, the records
+/// committed before are rolled back.
+fn execute_atomic(changeset: &ChangeSet, options: &Options, completed: &mut Vec<PathBuf>, summary: &mut Summary) -> Result<(), ProgramError> {
+    let target = options.router.default();
+
+    // Files are staged at the path of their final location relative to the target directory
+    let root = normalize(&std::path::absolute(target).map_err(anyhow::Error::from)?);
+    let relative = |record: &Path, path: &Path| -> anyhow::Result<PathBuf> {
+        let resolved = normalize(&std::path::absolute(options.router.resolve(record, path))?);
+        return match resolved.strip_prefix(&root) {
+            Ok(relative) if relative.as_os_str().is_empty() => Err(anyhow::anyhow!("Atomic execution can not move to the target directory itself: {}", record.display())),
+            Ok(relative) => Ok(relative.to_path_buf()),
+            Err(_) => Err(anyhow::anyhow!("Atomic execution requires all targets in the target directory: {}", record.display())),
+        };
+    };
+
+    // All files are staged in a single directory, which requires all targets on the same
+    // filesystem as the target directory
+    for (record, action) in changeset.records().iter() {
+        if let Action::Move(path) = action {
+            relative(record, path)?;
+        }
+    }
+
+    // The staging directory must live on the same filesystem as the final targets to allow
+    // renaming the staged files into place
+    std::fs::create_dir_all(target)
+        .map_err(anyhow::Error::from)?;
+
+    let staging = tempfile::Builder::new()
+        .prefix(".mmv.staging.")
+        .tempdir_in(target)
+        .map_err(anyhow::Error::from)?;
+
+    let mut journal = open_journal(changeset, options)?;
+
+    // Staged files are always compared by their checksum, using the algorithm selected for
+    // verification if any
+    let hash = options.verify.unwrap_or_default();
+
+    // Stage all files which should be moved. Interrupting while staging leaves the source tree
+    // untouched, once committing started, the execution is completed
+    let moves = changeset.records().iter()
+        .filter_map(|(source, action)| match action {
+            Action::Move(path) => Some((changeset.path().join(source), relative(source, path))),
+            _ => None,
+        })
+        .map(|(source, path)| Ok((source, path?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut progress = Progress::new(moves.len() as u64, moves.iter()
+        .map(|(source, _)| file_size(source))
+        .sum());
+
+    for (source, path) in moves.iter() {
+        if interrupted() {
+            return Err(ProgramError::Interrupted);
+        }
+
+        let staged = staging.path().join(path);
+        let line = format!("{} {}", Paint::cyan("⧗").bold(), root.join(path).display());
+
+        let stage = |source: &Path, staged: &Path| -> anyhow::Result<()> {
+            copy_file(source, staged, options)?;
+            preserve_attributes(source, staged, options.preserve)?;
+            sync_file(staged)?;
+            return Ok(());
+        };
+
+        let kind = std::fs::symlink_metadata(source)
+            .map_err(anyhow::Error::from)?
+            .file_type();
+
+        let result = staged.parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| if kind.is_symlink() && options.symlinks == SymlinkMode::Preserve {
+                copy_symlink(source, &staged, &root.join(path))
+            } else if is_special(kind) {
+                copy_special(source, &staged)
+            } else if kind.is_dir() {
+                // The files in a directory are verified while staging them
+                copy_dir(source, &staged, &|source, staged| {
+                    stage(source, staged)?;
+                    return verify_copy(source, staged, hash);
+                })
+            } else {
+                stage(source, &staged)
+            });
+
+        if let Err(err) = result {
+            progress.failed(&line);
+            return Err(err.into());
+        }
+
+        progress.succeeded(&line, file_size(source), true);
+    }
+
+    progress.finish();
 
-    // Execute actions in two steps: first, copy files which should be moved, second delete files
-    // either because they are moved or marked for deletion
+    // Verify all staged files before touching anything outside of the staging directory
     for (source, action) in changeset.records().iter() {
-        let source = changeset.path().join(&source);
+        if let Action::Move(path) = action {
+            let staged = staging.path().join(relative(source, path)?);
+            let source = changeset.path().join(source);
 
-        match action {
-            Action::Move(path) => {
-                let target = target.join(path);
+            // Preserved symlinks and recreated FIFOs have no content to compare
+            let kind = std::fs::symlink_metadata(&source)
+                .map_err(anyhow::Error::from)?
+                .file_type();
+            if (kind.is_symlink() && options.symlinks == SymlinkMode::Preserve) || is_special(kind) {
+                continue;
+            }
+
+            if !kind.is_dir() {
+                verify_copy(&source, &staged, hash)?;
+            }
+        }
+    }
+
+    // Commit the staged files to their final location in the same order as a regular execution,
+    // so each source is removed before another file is moved there and the journal can be undone.
+    // Sources are moved aside instead of being deleted, so all renames done while committing can
+    // be reverted if a record fails
+    let originals = tempfile::Builder::new()
+        .prefix(".mmv.originals.")
+        .tempdir_in(changeset.path())
+        .map_err(anyhow::Error::from)?;
 
-                print!("{} {} ", Paint::cyan("➤").bold(), target.display());
+    let journaled = journal.len()?;
+    let committed = completed.len();
+    let before = summary.clone();
 
-                if let Some(parent) = target.parent() {
-                    std::fs::create_dir_all(parent)
+    let mut renamed = Vec::<(PathBuf, PathBuf)>::new();
+    let stash = |source: &Path, renamed: &mut Vec<(PathBuf, PathBuf)>| -> anyhow::Result<()> {
+        let original = originals.path().join(renamed.len().to_string());
+        std::fs::rename(source, &original)?;
+        renamed.push((source.to_path_buf(), original));
+        return Ok(());
+    };
+
+    let mut commit = || -> Result<(), ProgramError> {
+        for step in schedule(changeset, options.router)?.into_iter().flatten() {
+            match step {
+                // The first step of a cycle moves the source out of the way without copying it
+                Step::Move { source, target: temporary, last: false, .. } => {
+                    std::fs::rename(&source, &temporary)
                         .map_err(anyhow::Error::from)?;
+                    renamed.push((source.clone(), temporary.clone()));
+
+                    journal.record(JournalEntry::moved(&source, &temporary)?)?;
+                }
+
+                Step::Move { record, source, target, last: true } => {
+                    let path = match &changeset.records()[record] {
+                        Action::Move(path) => path,
+                        _ => unreachable!("Move step without move action"),
+                    };
+                    let staged = staging.path().join(relative(record, path)?);
+
+                    if verbosity::enabled(Verbosity::Normal) {
+                        print!("{} {} ", Paint::cyan("➤").bold(), target.display());
+                    }
+
+                    let started = Instant::now();
+                    let bytes = file_size(&staged);
+
+                    let result = (|| -> anyhow::Result<()> {
+                        if let Some(parent) = target.parent() {
+                            std::fs::create_dir_all(parent)?;
+                        }
+
+                        if let Some(entry) = backup_file(&target, options)? {
+                            if let JournalEntry::Move { source, target } = &entry {
+                                renamed.push((source.clone(), target.clone()));
+                            }
+                            journal.record(entry)?;
+                        }
+
+                        stash(&source, &mut renamed)?;
+
+                        std::fs::rename(&staged, &target)?;
+                        renamed.push((staged.clone(), target.clone()));
+
+                        journal.record(JournalEntry::moved(&source, &target)?)?;
+
+                        return Ok(());
+                    })();
+
+                    if let Some(log) = options.log.as_ref() {
+                        let operation = Operation {
+                            record,
+                            action: "move",
+                            source: &source,
+                            target: Some(&target),
+                            bytes,
+                            duration: started.elapsed(),
+                        };
+
+                        if let Err(err) = log.lock().expect("Log poisoned").record(operation, result.as_ref().err()) {
+                            warn!("{}", err);
+                        }
+                    }
+
+                    if result.is_err() {
+                        info!("{}", Paint::red("✗").bold());
+                        summary.failed += 1;
+                    }
+                    result?;
+
+                    completed.push(record.to_path_buf());
+                    summary.moved += 1;
+                    summary.bytes += bytes;
+
+                    info!("{}", Paint::green("✓").bold());
+
+                    if let Err(err) = record_hook(changeset, record, options) {
+                        warn!("{}", err);
+                    }
+                }
+
+                Step::Delete { record, source } => {
+                    if verbosity::enabled(Verbosity::Normal) {
+                        print!("{} {} ", Paint::red("✕").bold(), source.display());
+                    }
+
+                    let started = Instant::now();
+
+                    let result = stash(&source, &mut renamed)
+                        .and_then(|()| journal.record(JournalEntry::deleted(&source)?));
+
+                    if let Some(log) = options.log.as_ref() {
+                        let operation = Operation {
+                            record,
+                            action: "delete",
+                            source: &source,
+                            target: None,
+                            bytes: 0,
+                            duration: started.elapsed(),
+                        };
+
+                        if let Err(err) = log.lock().expect("Log poisoned").record(operation, result.as_ref().err()) {
+                            warn!("{}", err);
+                        }
+                    }
+
+                    if result.is_err() {
+                        info!("{}", Paint::red("✗").bold());
+                        summary.failed += 1;
+                    }
+                    result?;
+
+                    completed.push(record.to_path_buf());
+                    summary.deleted += 1;
+
+                    info!("{}", Paint::green("✓").bold());
+
+                    if let Err(err) = record_hook(changeset, record, options) {
+                        warn!("{}", err);
+                    }
                 }
 
-                reflink::reflink_or_copy(&source, &target)
-                    .map_err(anyhow::Error::from)?;
+                Step::Keep { record } => {
+                    completed.push(record.to_path_buf());
+                }
+            }
+        }
+
+        return Ok(());
+    };
 
-                std::fs::remove_file(&source)
-                    .map_err(anyhow::Error::from)?;
+    if let Err(err) = commit() {
+        warn!("Rolling back {} committed records", completed.len() - committed);
 
-                println!("{}", Paint::green("✓").bold());
+        let mut failed = false;
+        for (source, target) in renamed.iter().rev() {
+            if let Err(err) = std::fs::rename(target, source) {
+                warn!("{}: failed to restore from {}: {}", source.display(), target.display(), err);
+                failed = true;
             }
+        }
+
+        // The journal and the moved aside sources are kept if the rollback is incomplete to allow
+        // recovering manually
+        if failed {
+            let originals = originals.keep();
+            warn!("Sources not restored are kept in {}", originals.display());
+        } else {
+            journal.truncate(journaled)?;
+        }
+
+        completed.truncate(committed);
+        summary.moved = before.moved;
+        summary.deleted = before.deleted;
+        summary.bytes = before.bytes;
 
-            Action::Delete => {
-                print!("{} {} ", Paint::red("✕").bold(), source.display());
+        return Err(err);
+    }
+
+    originals.close()
+        .map_err(anyhow::Error::from)?;
+
+    staging.close()
+        .map_err(anyhow::Error::from)?;
+
+    return Ok(());
+}
+
+/// Asks the user how to handle an existing target.
+fn ask_conflict(target: &Path) -> Result<ConflictPolicy, ProgramError> {
+    loop {
+        print!("{} {} exists. Overwrite? [y]es / [n]o / [b]ackup ", Paint::yellow("?").bold(), target.display());
+        std::io::stdout().flush()
+            .map_err(anyhow::Error::from)?;
+
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer).map_err(anyhow::Error::from)? == 0 {
+            // Nothing more to read - do not touch the existing file
+            println!();
+            return Ok(ConflictPolicy::Skip);
+        }
+
+        match answer.trim() {
+            "y" | "yes" => return Ok(ConflictPolicy::Overwrite),
+            "n" | "no" => return Ok(ConflictPolicy::Skip),
+            "b" | "backup" => return Ok(ConflictPolicy::Backup),
+            _ => continue,
+        }
+    }
+}
+
+/// Asks the user for each action of the plan whether it should be executed in this run.
+///
+/// Declined actions are removed from the plan and returned.
+fn select_interactive(plan: &mut ChangeSet, router: &Router) -> Result<Vec<PathBuf>, ProgramError> {
+    let actions = plan.records().iter()
+        .filter(|(_, action)| !matches!(action, Action::Ignore(_)))
+        .map(|(record, action)| (record.clone(), action.clone()))
+        .collect::<Vec<_>>();
 
-                std::fs::remove_file(&source)
-                    .map_err(anyhow::Error::from)?;
+    let mut declined = Vec::new();
+    for (i, (record, action)) in actions.iter().enumerate() {
+        let description = match action {
+            Action::Move(path) => format!("{} {} → {}", Paint::cyan("➤").bold(), record.display(), router.resolve(record, path).display()),
+            Action::Delete => format!("{} {}", Paint::red("✕").bold(), record.display()),
+            Action::Ignore(_) => unreachable!(),
+        };
 
-                println!("{}", Paint::green("✓").bold());
+        let answer = loop {
+            print!("{} {} [y]es / [n]o / [a]ll / [q]uit ", Paint::yellow("?").bold(), description);
+            std::io::stdout().flush()
+                .map_err(anyhow::Error::from)?;
+
+            let mut answer = String::new();
+            if std::io::stdin().read_line(&mut answer).map_err(anyhow::Error::from)? == 0 {
+                // Nothing more to read - do not execute the remaining actions
+                println!();
+                break "q".to_string();
             }
 
-            Action::Ignore(_) => {}
+            match answer.trim() {
+                "y" | "yes" | "n" | "no" | "a" | "all" | "q" | "quit" => break answer.trim().to_string(),
+                _ => continue,
+            }
+        };
+
+        match answer.as_str() {
+            "n" | "no" => declined.push(record.clone()),
+            "a" | "all" => break,
+            "q" | "quit" => {
+                declined.extend(actions[i..].iter().map(|(record, _)| record.clone()));
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    for record in declined.iter() {
+        plan.records_mut().remove(record);
+    }
+
+    return Ok(declined);
+}
+
+fn open_journal(changeset: &ChangeSet, options: &Options) -> anyhow::Result<Journal> {
+    if options.resume {
+        return Journal::append(changeset.workspace());
+    } else {
+        return Journal::create(changeset.workspace(), options.router.default());
+    }
+}
+
+/// Moves an existing target out of the way if it was selected for backup.
+///
+/// The backup is named by appending the suffix to the target. If a file with that name exists
+/// already, a number is appended, i.e. `name~1`, `name~2` and so on, so no earlier backup is
+/// overwritten.
+///
+/// Returns the journal entry restoring the backup on undo.
+fn backup_file(target: &Path, options: &Options) -> anyhow::Result<Option<JournalEntry>> {
+    if !options.backups.contains(target) {
+        return Ok(None);
+    }
+
+    let mut name = target.as_os_str().to_owned();
+    name.push(&options.backup_suffix);
+
+    let mut backup = PathBuf::from(&name);
+    let mut counter = 0;
+    while backup.symlink_metadata().is_ok() {
+        counter += 1;
+
+        let mut numbered = name.clone();
+        numbered.push(counter.to_string());
+        backup = PathBuf::from(numbered);
+    }
+
+    std::fs::rename(target, &backup)?;
+
+    return Ok(Some(JournalEntry::moved(target, backup)?));
+}
+
+/// Moves a file in two phases to survive crashes.
+///
+/// Files on the same filesystem as the target are renamed directly. Otherwise, the source is
+/// copied to a temporary file next to the target, synced to disk and renamed to the target
+/// atomically. The source is only removed after the target is complete, so the final target path
+/// never contains a partially written file.
+///
+/// If linking, the temporary file is a hard link to the source instead of a copy. The source is
+/// kept in place if copying or linking without removing the sources.
+fn move_file(source: &Path, target: &Path, options: &Options) -> anyhow::Result<()> {
+    let parent = target.parent()
+        .ok_or_else(|| anyhow::anyhow!("Invalid target: {}", target.display()))?;
+    std::fs::create_dir_all(parent)?;
+
+    // Symlinks are recreated at the target to adjust relative links or replaced by a copy of the
+    // file they point to, unless symlinks are not handled at all
+    let kind = std::fs::symlink_metadata(source)?.file_type();
+    let rename = !kind.is_symlink() || options.symlinks == SymlinkMode::Skip;
+
+    // Bind mounts share the device but refuse renames across them, so fall back to copying
+    if rename && !options.keep_sources && same_filesystem(source, parent)? {
+        match std::fs::rename(source, target) {
+            Ok(()) => {
+                verbose!("renamed {} to {}", source.display(), target.display());
+                sync_dir(parent)?;
+                return Ok(());
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+                debug!("renaming {} failed, copying instead: {}", source.display(), err);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    let mut staged = OsString::from(".mmv.tmp.");
+    staged.push(std::process::id().to_string());
+    staged.push(".");
+    staged.push(target.file_name().unwrap_or_default());
+    let staged = parent.join(staged);
+
+    // Directories are staged as a whole, so the target appears complete or not at all
+    let is_dir = kind.is_dir();
+
+    let result = if is_dir {
+        copy_dir(source, &staged, &|source, copy| stage_file(source, copy, options))
+    } else if kind.is_symlink() && options.symlinks == SymlinkMode::Preserve {
+        copy_symlink(source, &staged, target)
+    } else if is_special(kind) {
+        copy_special(source, &staged)
+    } else {
+        stage_file(source, &staged, options)
+    };
+
+    let result = result
+        .and_then(|()| std::fs::rename(&staged, target).map_err(anyhow::Error::from));
+
+    if let Err(err) = result {
+        // Do not leave the partial copy behind
+        let _ = if is_dir { std::fs::remove_dir_all(&staged) } else { std::fs::remove_file(&staged) };
+        return Err(err);
+    }
+
+    sync_dir(parent)?;
+
+    if !options.keep_sources {
+        delete_file(source)?;
+    }
+
+    return Ok(());
+}
+
+/// Links or copies a single file to its staged location.
+fn stage_file(source: &Path, staged: &Path, options: &Options) -> anyhow::Result<()> {
+    if options.link {
+        verbose!("linking {}", source.display());
+        return std::fs::hard_link(source, staged)
+            .map_err(|err| anyhow::anyhow!("Failed to link {}: {}", source.display(), err));
+    }
+
+    copy_file(source, staged, options)?;
+    preserve_attributes(source, staged, options.preserve)?;
+    sync_file(staged)?;
+
+    if let Some(hash) = options.verify {
+        verify_copy(source, staged, hash)?;
+    }
+
+    return Ok(());
+}
+
+/// Recreates a FIFO with the permissions of the source.
+///
+/// Other special files are refused, as reading them would not terminate or fail.
+pub fn copy_special(source: &Path, target: &Path) -> anyhow::Result<()> {
+    let metadata = std::fs::symlink_metadata(source)?;
+    if !metadata.file_type().is_fifo() {
+        return Err(anyhow::anyhow!("Can not copy special file: {}", source.display()));
+    }
+
+    mkfifoat(CWD, target, Mode::from_raw_mode(metadata.mode() & 0o7777))?;
+
+    return Ok(());
+}
+
+/// Creates a symlink at `path` pointing to the same file as the symlink `source` would when placed
+/// at `location`.
+///
+/// Relative symlinks are adjusted to the new location, absolute ones are kept as they are.
+pub fn copy_symlink(source: &Path, path: &Path, location: &Path) -> anyhow::Result<()> {
+    let link = std::fs::read_link(source)?;
+
+    let link = if link.is_relative() {
+        let source = std::path::absolute(source)?;
+        let location = std::path::absolute(location)?;
+
+        let (Some(source), Some(location)) = (source.parent(), location.parent()) else {
+            return Err(anyhow::anyhow!("Invalid symlink: {}", source.display()));
+        };
+
+        relative_path(&normalize(location), &normalize(&source.join(link)))
+    } else {
+        link
+    };
+
+    std::os::unix::fs::symlink(link, path)?;
+
+    return Ok(());
+}
+
+/// Copies a directory recursively using `copy` for the contained files.
+///
+/// Symlinks are recreated as they are. The permissions and modification times of the directories
+/// are kept.
+pub fn copy_dir(source: &Path, target: &Path, copy: &dyn Fn(&Path, &Path) -> anyhow::Result<()>) -> anyhow::Result<()> {
+    std::fs::create_dir(target)?;
+
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let (source, target) = (entry.path(), target.join(entry.file_name()));
+
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&source, &target, copy)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(std::fs::read_link(&source)?, &target)?;
+        } else if is_special(file_type) {
+            copy_special(&source, &target)?;
+        } else {
+            copy(&source, &target)?;
+        }
+    }
+
+    let metadata = std::fs::metadata(source)?;
+    std::fs::set_permissions(target, metadata.permissions())?;
+    File::open(target)?.set_modified(metadata.modified()?)?;
+
+    return Ok(());
+}
+
+/// Copies the content of a file, sharing the data blocks with the source as requested.
+///
+/// Only copies transferring the data are throttled, as reflinks do not cause any IO.
+fn copy_file(source: &Path, target: &Path, options: &Options) -> anyhow::Result<()> {
+    match (options.reflink, &options.throttle) {
+        (ReflinkMode::Auto, None) => {
+            match reflink::reflink_or_copy(source, target)? {
+                None => verbose!("reflinked {}", source.display()),
+                Some(_) => verbose!("copied {}, reflink not supported", source.display()),
+            }
+        }
+        (ReflinkMode::Auto, Some(throttle)) => {
+            if reflink::reflink(source, target).is_err() {
+                verbose!("copied {} with limited bandwidth, reflink not supported", source.display());
+                throttle.copy(source, target)?;
+            } else {
+                verbose!("reflinked {}", source.display());
+            }
+        }
+        (ReflinkMode::Always, _) => {
+            reflink::reflink(source, target)
+                .map_err(|err| anyhow::anyhow!("Failed to reflink {}: {}", source.display(), err))?;
+            verbose!("reflinked {}", source.display());
+        }
+        (ReflinkMode::Never, None) => {
+            std::fs::copy(source, target)?;
+            verbose!("copied {}", source.display());
+        }
+        (ReflinkMode::Never, Some(throttle)) => {
+            throttle.copy(source, target)?;
+            verbose!("copied {} with limited bandwidth", source.display());
+        }
+    }
+
+    return Ok(());
+}
+
+/// Replicates the selected attributes of a file to its copy.
+fn preserve_attributes(source: &Path, copy: &Path, preserve: Preserve) -> anyhow::Result<()> {
+    let metadata = std::fs::metadata(source)?;
+
+    // The copy has the mode of the source already. Read-only copies are made writable for the
+    // owner while setting the extended attributes and restored afterwards
+    let readonly = metadata.permissions().mode() & 0o200 == 0;
+
+    if preserve.xattr || preserve.acl {
+        if readonly {
+            std::fs::set_permissions(copy, Permissions::from_mode(metadata.permissions().mode() | 0o200))?;
+        }
+
+        for name in xattr::list(source)? {
+            // POSIX ACLs are stored as extended attributes in the system namespace
+            let selected = match name.as_bytes().starts_with(b"system.posix_acl_") {
+                true => preserve.acl,
+                false => preserve.xattr,
+            };
+            if !selected {
+                continue;
+            }
+
+            if let Some(value) = xattr::get(source, &name)? {
+                xattr::set(copy, &name, &value)
+                    .map_err(|err| anyhow::anyhow!("Failed to set {} on {}: {}", name.to_string_lossy(), copy.display(), err))?;
+            }
+        }
+    }
+
+    // The timestamps are set after the other attributes as setting those may touch them. The owner
+    // can set them without write permission
+    if preserve.metadata {
+        let file = File::open(copy)?;
+        file.set_times(FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?))?;
+    }
+
+    // The permissions are set last, so a read-only source does not prevent setting the others
+    if preserve.metadata || (readonly && (preserve.xattr || preserve.acl)) {
+        std::fs::set_permissions(copy, metadata.permissions())?;
+    }
+
+    return Ok(());
+}
+
+/// Checks if all sources can be read and removed and all target directories can be written.
+///
+/// All missing permissions are reported before aborting.
+fn check_permissions(changeset: &ChangeSet, options: &Options) -> anyhow::Result<()> {
+    let writable = |dir: &Path| access(dir, Access::WRITE_OK | Access::EXEC_OK).is_ok();
+
+    let mut problems = BTreeMap::new();
+    for (record, action) in changeset.records().iter() {
+        let source = changeset.path().join(record);
+
+        match action {
+            Action::Move(path) => {
+                if access(&source, Access::READ_OK).is_err() {
+                    problems.insert(source.clone(), "source not readable");
+                }
+
+                // Missing directories are created in the nearest existing ancestor
+                let target = std::path::absolute(options.router.resolve(record, path))?;
+                if let Some(dir) = existing_dir(&target) {
+                    if !writable(dir) {
+                        problems.insert(dir.to_path_buf(), "target directory not writable");
+                    }
+                }
+
+                if options.keep_sources {
+                    continue;
+                }
+            }
+            Action::Delete => {}
+            Action::Ignore(_) => continue,
+        }
+
+        if let Some(dir) = source.parent().filter(|dir| dir.is_dir()) {
+            if !writable(dir) {
+                problems.insert(dir.to_path_buf(), "source directory not writable");
+            }
+        }
+    }
+
+    for (path, problem) in problems.iter() {
+        eprintln!("{} {}: {}", Paint::red("✗").bold(), path.display(), problem);
+    }
+
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!("Insufficient permissions"));
+    }
+
+    return Ok(());
+}
+
+/// Checks if the files copied by the plan fit on the target file systems.
+///
+/// Files renamed or linked on the same file system do not take any space, unless executing
+/// atomically, which copies all files into the staging directory. All file systems running out of
+/// space are reported before aborting.
+fn check_space(changeset: &ChangeSet, options: &Options, atomic: bool) -> anyhow::Result<()> {
+    // The required space per file system together with a directory on it
+    let mut required = BTreeMap::<u64, (PathBuf, u64)>::new();
+    for (record, action) in changeset.records().iter() {
+        let path = match action {
+            Action::Move(path) => path,
+            Action::Delete | Action::Ignore(_) => continue,
+        };
+
+        let source = match std::fs::symlink_metadata(changeset.path().join(record)) {
+            Ok(source) => source,
+            Err(_) => continue,
+        };
+
+        let target = std::path::absolute(options.router.resolve(record, path))?;
+        let dir = match existing_dir(&target) {
+            Some(dir) => dir.to_path_buf(),
+            None => continue,
+        };
+
+        let dev = std::fs::metadata(&dir)?.dev();
+        if !atomic && source.dev() == dev && (options.link || !options.keep_sources) {
+            continue;
+        }
+
+        required.entry(dev).or_insert((dir, 0)).1 += file_size(&changeset.path().join(record));
+    }
+
+    let mut exceeded = false;
+    for (dir, required) in required.values() {
+        let available = fs4::available_space(dir)?;
+        if *required > available {
+            eprintln!("{} {}: not enough space, {} required, {} available",
+                      Paint::red("✗").bold(), dir.display(),
+                      HumanBytes(*required), HumanBytes(available));
+            exceeded = true;
+        }
+    }
+
+    if exceeded {
+        return Err(anyhow::anyhow!("Not enough space on target file system"));
+    }
+
+    return Ok(());
+}
+
+/// Compares the checksums of a file and its copy.
+fn verify_copy(source: &Path, copy: &Path, hash: HashAlgorithm) -> anyhow::Result<()> {
+    if hash.checksum(source)? != hash.checksum(copy)? {
+        return Err(anyhow::anyhow!("Copy differs from source: {}", source.display()));
+    }
+
+    return Ok(());
+}
+
+/// Returns the size of a file or the total size of the files in a directory, which is zero if it
+/// can not be determined. Symlinks count with the size of the file they point to.
+fn file_size(path: &Path) -> u64 {
+    return match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_symlink() => std::fs::metadata(path)
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map_or(0, |metadata| metadata.len()),
+        Ok(metadata) if metadata.is_dir() => std::fs::read_dir(path)
+            .map(|entries| entries
+                .filter_map(Result::ok)
+                .map(|entry| file_size(&entry.path()))
+                .sum())
+            .unwrap_or(0),
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+}
+
+/// Returns the nearest existing ancestor of a target.
+///
+/// The target directory may not exist yet, but will be created on the same file system as its
+/// nearest existing ancestor.
+fn existing_dir(target: &Path) -> Option<&Path> {
+    return target.ancestors().skip(1).find(|dir| dir.is_dir());
+}
+
+/// Checks if moving a file to a target copies it to another file system.
+fn crosses_device(source: &Path, target: &Path) -> bool {
+    let source = match std::fs::symlink_metadata(source) {
+        Ok(source) => source,
+        Err(_) => return false,
+    };
+
+    let target = match std::path::absolute(target) {
+        Ok(target) => target,
+        Err(_) => return false,
+    };
+
+    return existing_dir(&target)
+        .and_then(|dir| std::fs::metadata(dir).ok())
+        .is_some_and(|dir| dir.dev() != source.dev());
+}
+
+/// Checks if a file can be renamed into a directory without copying it.
+fn same_filesystem(source: &Path, dir: &Path) -> anyhow::Result<bool> {
+    let source = std::fs::symlink_metadata(source)?;
+    let dir = std::fs::metadata(dir)?;
+
+    return Ok(source.dev() == dir.dev());
+}
+
+/// Leaves symlinks at the old locations of the completed moves.
+///
+/// Old locations which are the target of any record or which exist otherwise are skipped. The files
+/// have been moved already, so failing to leave a symlink does not fail the execution.
+fn leave_symlinks(changeset: &ChangeSet, options: &Options, completed: &[PathBuf], journal: &mut Journal) {
+    let targets = changeset.records().iter()
+        .filter_map(|(record, action)| match action {
+            Action::Move(path) => std::path::absolute(options.router.resolve(record, path)).ok(),
+            _ => None,
+        })
+        .map(|target| normalize(&target))
+        .collect::<BTreeSet<_>>();
+
+    for record in completed.iter() {
+        let path = match &changeset.records()[record] {
+            Action::Move(path) => path,
+            _ => continue,
+        };
+
+        let origin = options.source.join(record);
+        let taken = std::path::absolute(&origin).map_or(true, |origin| targets.contains(&normalize(&origin)));
+        if taken || origin.symlink_metadata().is_ok() {
+            continue;
+        }
+
+        let result = leave_symlink(&origin, &options.router.resolve(record, path))
+            .and_then(|entry| journal.record(entry));
+        if let Err(err) = result {
+            warn!("Failed to leave symlink at {}: {}", origin.display(), err);
         }
     }
+}
+
+/// Creates a symlink at the old location of a moved file pointing to the new location.
+///
+/// The symlink is relative to stay valid if both trees are moved together.
+fn leave_symlink(origin: &Path, target: &Path) -> anyhow::Result<JournalEntry> {
+    let origin = normalize(&std::path::absolute(origin)?);
+    let target = normalize(&std::path::absolute(target)?);
+
+    let base = origin.parent()
+        .ok_or_else(|| anyhow::anyhow!("Invalid source: {}", origin.display()))?;
+
+    std::os::unix::fs::symlink(relative_path(base, &target), &origin)?;
+
+    return JournalEntry::symlinked(&origin, &target);
+}
+
+/// Returns the path of `target` relative to the directory `base`, both being absolute.
+fn relative_path(base: &Path, target: &Path) -> PathBuf {
+    let base = base.components().collect::<Vec<_>>();
+    let target = target.components().collect::<Vec<_>>();
+
+    let common = base.iter().zip(target.iter())
+        .take_while(|(base, target)| base == target)
+        .count();
+
+    let mut path = PathBuf::new();
+    for _ in common..base.len() {
+        path.push("..");
+    }
+    for component in target[common..].iter() {
+        path.push(component);
+    }
+
+    return path;
+}
+
+/// Flushes the content of a file to disk.
+fn sync_file(path: &Path) -> anyhow::Result<()> {
+    File::open(path)?.sync_all()?;
+
+    return Ok(());
+}
 
-    // TODO: Continue after error
-    // TODO: Update changeset with moved / deleted files
-    // TODO: Clean empty parent directories
+/// Flushes a directory to disk to persist renames of its entries.
+fn sync_dir(path: &Path) -> anyhow::Result<()> {
+    File::open(path)?.sync_all()?;
+
+    return Ok(());
+}
+
+fn delete_file(source: &Path) -> anyhow::Result<()> {
+    if std::fs::symlink_metadata(source)?.is_dir() {
+        std::fs::remove_dir_all(source)?;
+    } else {
+        std::fs::remove_file(source)?;
+    }
 
     return Ok(());
 }
@@ -70,8 +2228,207 @@ pub fn subcommand() -> App<'static, 'static> {
             .short("t")
             .long("target")
             .value_name("DIR")
-            .help("The target directory to move files to")
+            .help("The target directory to move files to, defaults to the workspace")
+            .takes_value(true)
+            .index(1))
+        .args(&args());
+}
+
+/// The options controlling the execution shared with `resume`.
+pub fn args() -> Vec<Arg<'static, 'static>> {
+    return vec![
+        Arg::with_name("atomic")
+            .long("atomic")
+            .takes_value(false)
+            .help("Stage all files in the target before moving any of them into place"),
+        Arg::with_name("link")
+            .long("link")
+            .takes_value(false)
+            .conflicts_with("atomic")
+            .help("Create hard links at the targets and leave the sources in place"),
+        Arg::with_name("copy")
+            .long("copy")
+            .takes_value(false)
+            .conflicts_with_all(&["atomic", "link"])
+            .help("Copy the files to the targets and leave the sources in place"),
+        Arg::with_name("leave-symlink")
+            .long("leave-symlink")
+            .takes_value(false)
+            .conflicts_with_all(&["atomic", "copy"])
+            .help("Leave a relative symlink to the new location at the old location of moved files"),
+        Arg::with_name("remove-sources")
+            .long("remove-sources")
+            .takes_value(false)
+            .requires("link")
+            .help("Remove the sources after linking them"),
+        Arg::with_name("dry-run")
+            .short("n")
+            .long("dry-run")
+            .takes_value(false)
+            .help("Print the actions without touching any file"),
+        Arg::with_name("allow-outside")
+            .long("allow-outside")
+            .takes_value(false)
+            .help("Allow targets resolving outside of the target directory"),
+        Arg::with_name("allow-absolute")
+            .long("allow-absolute")
+            .takes_value(false)
+            .help("Allow absolute targets which are not placed in the target directory"),
+        Arg::with_name("expand")
+            .long("expand")
+            .takes_value(false)
+            .help("Expand environment variables ($VAR, ${VAR}) and ~ in targets, allowing absolute targets"),
+        Arg::with_name("no-permission-check")
+            .long("no-permission-check")
+            .takes_value(false)
+            .help("Do not check the permissions on sources and target directories before executing"),
+        Arg::with_name("git")
+            .long("git")
+            .takes_value(false)
+            .conflicts_with_all(&["copy", "link"])
+            .help("Record the moves and deletions in the index of the git repository containing the workspace, like git mv and git rm"),
+        Arg::with_name("commit")
+            .long("commit")
+            .value_name("MESSAGE")
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .conflicts_with_all(&["copy", "link"])
+            .help("Commit the moves and deletions to the git repository, implies --git"),
+        Arg::with_name("no-hooks")
+            .long("no-hooks")
+            .takes_value(false)
+            .help("Do not run the configured hooks"),
+        Arg::with_name("no-cross-device")
+            .long("no-cross-device")
+            .takes_value(false)
+            .help("Refuse to execute if any file would be copied to another device instead of renamed"),
+        Arg::with_name("no-space-check")
+            .long("no-space-check")
+            .takes_value(false)
+            .help("Do not check for enough free space on the target file systems before copying"),
+        Arg::with_name("portable-names")
+            .long("portable-names")
+            .takes_value(false)
+            .help("Reject target names which are not valid on Windows file systems"),
+        Arg::with_name("json")
+            .long("json")
+            .takes_value(false)
+            .requires("dry-run")
+            .help("Print the planned actions as JSON"),
+        Arg::with_name("log-file")
+            .long("log-file")
+            .value_name("FILE")
+            .help("Append a machine-readable log of all file operations to the file")
+            .takes_value(true),
+        Arg::with_name("keep-going")
+            .short("k")
+            .long("keep-going")
+            .takes_value(false)
+            .help("Continue with the remaining records if an action fails"),
+        Arg::with_name("bwlimit")
+            .long("bwlimit")
+            .value_name("RATE")
+            .help("Limit the throughput of copies in bytes per second, i.e. 10M")
+            .takes_value(true),
+        Arg::with_name("jobs")
+            .short("j")
+            .long("jobs")
+            .value_name("N")
+            .help("Number of independent records moved concurrently (defaults to 1)")
+            .takes_value(true)
+            .conflicts_with("atomic"),
+        Arg::with_name("no-preserve")
+            .long("no-preserve")
+            .takes_value(false)
+            .help("Do not replicate the permissions and timestamps of the sources to copied files"),
+        Arg::with_name("preserve")
+            .long("preserve")
+            .value_name("ATTRS")
+            .help("Additionally replicate extended attributes and ACLs to copied files")
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true)
+            .require_equals(true)
+            .possible_values(&["xattr", "acl"]),
+        Arg::with_name("verify")
+            .long("verify")
+            .takes_value(false)
+            .help("Compare the checksums of copied files before removing the sources"),
+        Arg::with_name("no-verify")
+            .long("no-verify")
+            .takes_value(false)
+            .conflicts_with("verify")
+            .help("Do not compare checksums, even if enabled in the configuration"),
+        Arg::with_name("hash")
+            .long("hash")
+            .value_name("ALGORITHM")
+            .help("The algorithm used for checksums, defaults to the configured one or blake3")
+            .takes_value(true)
+            .possible_values(HashAlgorithm::NAMES),
+        Arg::with_name("keep-empty-dirs")
+            .long("keep-empty-dirs")
+            .takes_value(false)
+            .help("Keep directories which became empty in the source tree"),
+        Arg::with_name("only")
+            .long("only")
+            .value_name("GLOB")
+            .help("Execute only the records with a source matching the pattern, can be given multiple times")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("route")
+            .long("route")
+            .value_name("GLOB=DIR")
+            .help("Move records with a source matching the pattern to another target directory, can be given multiple times")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("routes")
+            .long("routes")
+            .value_name("FILE")
+            .help("Read routes from a file containing one GLOB=DIR rule per line")
+            .takes_value(true),
+        Arg::with_name("moves-only")
+            .long("moves-only")
+            .takes_value(false)
+            .conflicts_with("deletes-only")
+            .help("Execute only the records moving files"),
+        Arg::with_name("deletes-only")
+            .long("deletes-only")
+            .takes_value(false)
+            .help("Execute only the records deleting files"),
+        Arg::with_name("interactive")
+            .short("i")
+            .long("interactive")
+            .takes_value(false)
+            .conflicts_with("json")
+            .help("Ask for each action whether it should be executed"),
+        Arg::with_name("auto-rename")
+            .long("auto-rename")
+            .takes_value(false)
+            .help("Append a number to targets colliding with other targets or existing files"),
+        Arg::with_name("backup")
+            .long("backup")
+            .value_name("SUFFIX")
+            .help("Back up existing targets instead of overwriting them by appending a suffix, defaults to ~, followed by a number if the backup exists")
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .empty_values(false),
+        Arg::with_name("on-conflict")
+            .long("on-conflict")
+            .value_name("POLICY")
+            .help("How to handle targets which exist already")
+            .takes_value(true)
+            .possible_values(&["skip", "overwrite", "backup", "ask"])
+            .default_value("overwrite"),
+        Arg::with_name("reflink")
+            .long("reflink")
+            .value_name("WHEN")
+            .help("Whether copies share the data with the source, always fails on filesystems without support")
             .takes_value(true)
-            .index(1)
-            .required(true));
+            .possible_values(&["auto", "always", "never"])
+            .default_value("auto"),
+    ];
 }
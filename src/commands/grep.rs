@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use regex::RegexBuilder;
+
+use crate::changeset::{Action, Workspace};
+use crate::commands::list::print_record;
+use crate::escape::escape;
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let pattern = RegexBuilder::new(matches.value_of("pattern").expect("No pattern"))
+        .case_insensitive(matches.is_present("ignore-case"))
+        .build()
+        .map_err(anyhow::Error::from)?;
+
+    // Without restriction, both sources and targets are searched
+    let both = !matches.is_present("sources") && !matches.is_present("targets");
+    let sources = both || matches.is_present("sources");
+    let targets = both || matches.is_present("targets");
+
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    let changeset = workspace.import()?;
+
+    let width = changeset.records().len().to_string().len();
+
+    let mut count = 0;
+    for (id, source, action) in changeset.ordered() {
+        let source_matches = sources && pattern.is_match(&escape(source));
+        let target_matches = targets && match action {
+            Action::Move(target) => pattern.is_match(&escape(target)),
+            Action::Delete | Action::Ignore(_) => false,
+        };
+
+        if !source_matches && !target_matches {
+            continue;
+        }
+
+        count += 1;
+
+        if !matches.is_present("count") {
+            print_record(id, width, source, action);
+        }
+    }
+
+    if matches.is_present("count") {
+        println!("{}", count);
+    }
+
+    return Ok(());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("grep")
+        .about("Searches the sources and targets of the change set")
+        .arg(Arg::with_name("pattern")
+            .value_name("PATTERN")
+            .help("The regular expression to search for")
+            .required(true)
+            .index(1))
+        .arg(Arg::with_name("sources")
+            .short("s")
+            .long("sources")
+            .takes_value(false)
+            .help("Only search the sources"))
+        .arg(Arg::with_name("targets")
+            .short("t")
+            .long("targets")
+            .takes_value(false)
+            .help("Only search the targets of moved records"))
+        .arg(Arg::with_name("ignore-case")
+            .short("i")
+            .long("ignore-case")
+            .takes_value(false)
+            .help("Search case insensitive"))
+        .arg(Arg::with_name("count")
+            .short("c")
+            .long("count")
+            .takes_value(false)
+            .help("Only print the number of matching records"));
+}
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use yansi::Paint;
+
+use crate::changeset::Workspace;
+use crate::ignorelist::IgnoreList;
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    // The ignore list is independent from the change set and can be maintained before the
+    // workspace is initialized
+    let workspace = Workspace::at(workspace);
+
+    let ignores = IgnoreList::load(&workspace)?;
+
+    match matches.subcommand() {
+        ("add", Some(matches)) => {
+            let mut ignores = ignores;
+            for pattern in matches.values_of("pattern").expect("No pattern") {
+                if ignores.patterns().iter().any(|p| p == pattern) {
+                    continue;
+                }
+
+                ignores = ignores.add(pattern)?;
+                info!("{} {}", Paint::green("+").bold(), pattern);
+            }
+
+            ignores.save(&workspace)?;
+        }
+
+        ("remove", Some(matches)) => {
+            let mut ignores = ignores;
+            for pattern in matches.values_of("pattern").expect("No pattern") {
+                if !ignores.patterns().iter().any(|p| p == pattern) {
+                    continue;
+                }
+
+                ignores = ignores.remove(pattern)?;
+                info!("{} {}", Paint::red("-").bold(), pattern);
+            }
+
+            ignores.save(&workspace)?;
+        }
+
+        ("list", Some(_)) => {
+            for pattern in ignores.patterns() {
+                println!("{}", pattern);
+            }
+        }
+
+        _ => unreachable!()
+    }
+
+    return Ok(());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("ignore")
+        .about("Maintains the list of files permanently excluded from the change set")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("add")
+            .about("Adds patterns to the ignore list")
+            .arg(Arg::with_name("pattern")
+                .value_name("GLOB")
+                .help("The glob pattern to ignore")
+                .multiple(true)
+                .required(true)))
+        .subcommand(SubCommand::with_name("remove")
+            .about("Removes patterns from the ignore list")
+            .alias("rm")
+            .arg(Arg::with_name("pattern")
+                .value_name("GLOB")
+                .help("The glob pattern to remove")
+                .multiple(true)
+                .required(true)))
+        .subcommand(SubCommand::with_name("list")
+            .about("Lists all ignored patterns")
+            .alias("ls"));
+}
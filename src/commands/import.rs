@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use yansi::Paint;
+
+use crate::changeset::{Action, Workspace};
+use crate::escape::unescape;
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let delimiter = match matches.value_of("format").expect("No format") {
+        "csv" => b',',
+        "tsv" => b'\t',
+        format => return Err(anyhow!("Invalid format: {}", format).into()),
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(matches.is_present("header"))
+        .flexible(false)
+        .from_path(matches.value_of("file").expect("No file"))
+        .map_err(anyhow::Error::from)?;
+
+    let mut mappings = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(anyhow::Error::from)?;
+        match (record.get(0), record.get(1), record.len()) {
+            (Some(source), Some(target), 2) => {
+                let source = unescape(source);
+                let source = source.strip_prefix(".").unwrap_or(&source).to_path_buf();
+                mappings.push((source, Action::from(target)));
+            }
+            _ => {
+                let line = record.position().map(|position| position.line()).unwrap_or_default();
+                return Err(anyhow!("Expected source and target in line {}", line).into());
+            }
+        }
+    }
+
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    let changeset = workspace.import()?;
+    let mut changeset = changeset.clean()
+        .ok_or_else(|| ProgramError::NotClean)?;
+
+    // Check all sources before changing any record
+    let unknown = mappings.iter()
+        .map(|(source, _)| source)
+        .filter(|source| !changeset.records().contains_key(*source))
+        .collect::<Vec<&PathBuf>>();
+    if !unknown.is_empty() {
+        for source in unknown.iter() {
+            eprintln!("{} {}", Paint::red("?").bold(), source.display());
+        }
+
+        return Err(anyhow!("Unknown sources: {}", unknown.len()).into());
+    }
+
+    for (source, action) in mappings {
+        match &action {
+            Action::Move(target) => info!("{} {} {} {}", Paint::yellow("~").bold(), source.display(), Paint::yellow("→"), target.display()),
+            Action::Delete => info!("{} {}", Paint::red("✕").bold(), source.display()),
+            Action::Ignore(_) => info!("{} {}", Paint::new("·").dimmed(), source.display()),
+        }
+
+        changeset.records_mut().insert(source, action);
+    }
+
+    if !matches.is_present("dry-run") {
+        changeset.export()?;
+    }
+
+    return Ok(());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("import")
+        .about("Merges source and target pairs from a file into the change set")
+        .after_help("Each row must consist of the source and the target. The target follows the format of the \
+            targets file: an empty target marks the source for deletion and a target starting with whitespace \
+            ignores the source.")
+        .arg(Arg::with_name("file")
+            .value_name("FILE")
+            .help("The file containing the mappings")
+            .required(true)
+            .index(1))
+        .arg(Arg::with_name("format")
+            .long("format")
+            .value_name("FORMAT")
+            .possible_values(&["csv", "tsv"])
+            .default_value("csv")
+            .help("The format of the file")
+            .takes_value(true))
+        .arg(Arg::with_name("header")
+            .long("header")
+            .takes_value(false)
+            .help("Skip the first row of the file"))
+        .arg(Arg::with_name("dry-run")
+            .short("n")
+            .long("dry-run")
+            .takes_value(false)
+            .help("Print the imported records without updating the change set"));
+}
@@ -1,29 +1,94 @@
+use std::collections::BTreeMap;
 use std::path::Path;
 
 use clap::{ArgMatches, SubCommand, App, Arg};
 use yansi::Paint;
 
-use crate::{ProgramError, scan_tree};
-use crate::changeset::{Action, ChangeSet, Workspace};
+use crate::ProgramError;
+use crate::config::Config;
+use crate::escape::escape;
+use crate::changeset::{Action, ChangeSet, Format, Workspace};
+use crate::fingerprint::Fingerprints;
+use crate::ignorelist::IgnoreList;
+use crate::order::Order;
+use crate::scan::{scan_tree, ScanFilter};
 
 pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
-    let workspace = Workspace::at(workspace);
+    let path = workspace;
+    let workspace = Workspace::selected(path);
 
     if !matches.is_present("force") && workspace.is_initialized() {
-        eprintln!("{} {}", Paint::red("Already initialized."), "Use -f to reset");
+        eprintln!("{} Use -f to reset", Paint::red("Already initialized."));
         return Ok(());
     }
 
-    let records = scan_tree(workspace.path())
+    // The state is kept outside of the directory once the external directory exists
+    let workspace = if matches.is_present("external") {
+        let external = Workspace::external(path)
+            .ok_or_else(|| anyhow::anyhow!("No state directory, set $XDG_STATE_HOME or $HOME"))?;
+        std::fs::create_dir_all(&external).map_err(anyhow::Error::from)?;
+
+        Workspace::selected(path)
+    } else {
+        workspace
+    };
+
+    // Keep the format and the order of an existing workspace unless requested otherwise
+    let workspace = match matches.value_of("format") {
+        Some(format) => workspace.convert(format.parse::<Format>()?)?,
+        None => workspace,
+    };
+    let workspace = match matches.value_of("sort") {
+        Some(order) => workspace.with_order(order.parse::<Order>()?),
+        None => workspace,
+    };
+
+    let config = Config::load()?;
+    let ignores = IgnoreList::load(&workspace)?
+        .with_global(&config)?;
+
+    // Persist the filter used for the initial scan, which drops the filter of a previous
+    // initialization. Without a filter, the configured one is used for every scan
+    let filter = match ScanFilter::from_matches(matches)? {
+        Some(filter) => {
+            filter.save(&workspace)?;
+            filter
+        }
+        None => {
+            if workspace.filter_path().is_file() {
+                std::fs::remove_file(workspace.filter_path()).map_err(anyhow::Error::from)?;
+            }
+            ScanFilter::configured(&workspace)?
+        }
+    };
+
+    let records = scan_tree(workspace.path(), &filter)?
+        .filter(|path| {
+            if ignores.is_ignored(path) {
+                verbose!("skipping {}: ignored", path.display());
+                return false;
+            }
+
+            return true;
+        })
         .map(|path| {
-            return (path.clone(), Action::Ignore(path.display().to_string()));
+            return (path.clone(), Action::Ignore(escape(&path)));
         })
-        .collect();
+        .collect::<BTreeMap<_, _>>();
+
+    // Fingerprints of a previous initialization are dropped unless requested again
+    if matches.is_present("fingerprint") {
+        Fingerprints::new(config.checksum.algorithm.unwrap_or_default())
+            .update(workspace.path(), records.keys())?
+            .save(&workspace)?;
+    } else if workspace.fingerprints_path().is_file() {
+        std::fs::remove_file(workspace.fingerprints_path()).map_err(anyhow::Error::from)?;
+    }
 
     let changeset = ChangeSet::create(workspace, records);
     changeset.export()?;
 
-    println!("{}", Paint::green("Initialized"));
+    info!("{}", Paint::green("Initialized"));
 
     return Ok(());
 }
@@ -35,5 +100,21 @@ pub fn subcommand() -> App<'static, 'static> {
             .short("f")
             .long("force")
             .takes_value(false)
-            .help("Re-initialize even if already initialized"));
+            .help("Re-initialize even if already initialized"))
+        .arg(Arg::with_name("external")
+            .long("external")
+            .takes_value(false)
+            .help("Keep the change set in $XDG_STATE_HOME/mmv instead of the directory"))
+        .arg(Arg::with_name("fingerprint")
+            .long("fingerprint")
+            .takes_value(false)
+            .help("Remember the checksums of all files to detect files renamed outside of mmv on update"))
+        .arg(Arg::with_name("format")
+            .long("format")
+            .value_name("FORMAT")
+            .possible_values(&["split", "combined"])
+            .help("Store sources and targets in separate files (split) or tab-separated in a single file (combined)")
+            .takes_value(true))
+        .arg(Order::arg())
+        .args(&ScanFilter::args());
 }
\ No newline at end of file
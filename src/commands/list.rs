@@ -0,0 +1,90 @@
+use std::path::Path;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use globset::Glob;
+use yansi::Paint;
+
+use crate::changeset::{Action, Workspace};
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let filter = matches.value_of("filter")
+        .map(|filter| Glob::new(filter).map(|glob| glob.compile_matcher()))
+        .transpose()
+        .map_err(anyhow::Error::from)?;
+
+    // Without any action type selected, all records are listed
+    let all = !matches.is_present("moves") && !matches.is_present("deletes") && !matches.is_present("ignores");
+    let moves = all || matches.is_present("moves");
+    let deletes = all || matches.is_present("deletes");
+    let ignores = all || matches.is_present("ignores");
+
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    let changeset = workspace.import()?;
+
+    let width = changeset.records().len().to_string().len();
+
+    for (id, source, action) in changeset.ordered() {
+        if let Some(filter) = filter.as_ref() {
+            if !filter.is_match(source) {
+                continue;
+            }
+        }
+
+        let selected = match action {
+            Action::Move(_) => moves,
+            Action::Delete => deletes,
+            Action::Ignore(_) => ignores,
+        };
+
+        if selected {
+            print_record(id, width, source, action);
+        }
+    }
+
+    return Ok(());
+}
+
+/// Prints a single record prefixed with its ID padded to the given width.
+pub fn print_record(id: usize, width: usize, source: &Path, action: &Action) {
+    let id = format!("{:>width$}", id, width = width);
+
+    match action {
+        Action::Move(target) => {
+            println!("{} {} {} {} {}", id, Paint::cyan("➤").bold(), source.display(), Paint::cyan("→"), target.display());
+        }
+        Action::Delete => {
+            println!("{} {} {}", id, Paint::red("✕").bold(), source.display());
+        }
+        Action::Ignore(comment) => {
+            println!("{} {} {} {}", id, Paint::new("·").dimmed(), source.display(), Paint::new(comment).dimmed());
+        }
+    }
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("list")
+        .alias("ls")
+        .about("Lists the records of the change set")
+        .arg(Arg::with_name("filter")
+            .value_name("GLOB")
+            .help("Only list records with a source matching the pattern")
+            .index(1))
+        .arg(Arg::with_name("moves")
+            .short("m")
+            .long("moves")
+            .takes_value(false)
+            .help("List records which are moved"))
+        .arg(Arg::with_name("deletes")
+            .short("d")
+            .long("deletes")
+            .takes_value(false)
+            .help("List records which are deleted"))
+        .arg(Arg::with_name("ignores")
+            .short("i")
+            .long("ignores")
+            .takes_value(false)
+            .help("List records which are ignored"));
+}
@@ -0,0 +1,171 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use yansi::Paint;
+
+use crate::ProgramError;
+
+pub fn run(_workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    if let Some(output) = matches.value_of("output") {
+        let output = Path::new(output);
+        std::fs::create_dir_all(output).map_err(anyhow::Error::from)?;
+
+        std::fs::write(output.join("mmv.1"), page(crate::app(), None)?).map_err(anyhow::Error::from)?;
+        for subcommand in crate::subcommands() {
+            let name = subcommand.get_name().to_string();
+            std::fs::write(output.join(format!("mmv-{}.1", name)), page(subcommand, Some(&name))?)
+                .map_err(anyhow::Error::from)?;
+        }
+
+        println!("{} {} man pages to {}",
+                 Paint::green("Wrote"),
+                 crate::subcommands().len() + 1,
+                 output.display());
+
+        return Ok(());
+    }
+
+    let page = match matches.value_of("command") {
+        Some(name) => {
+            let subcommand = crate::subcommands().into_iter()
+                .find(|subcommand| subcommand.get_name() == name)
+                .ok_or_else(|| anyhow!("Unknown command: {}", name))?;
+            page(subcommand, Some(name))?
+        }
+        None => page(crate::app(), None)?,
+    };
+
+    print!("{}", page);
+
+    return Ok(());
+}
+
+/// Renders the man page of the main command or one of its subcommands in roff format.
+///
+/// The page is derived from the help of the command, which is rendered section by section using
+/// help templates.
+fn page(app: App<'static, 'static>, subcommand: Option<&str>) -> Result<String> {
+    let (title, name, bin) = match subcommand {
+        Some(subcommand) => (format!("mmv-{}", subcommand), format!("mmv-{}", subcommand), format!("mmv {}", subcommand)),
+        None => ("mmv".to_string(), "mmv".to_string(), "mmv".to_string()),
+    };
+
+    let app = app
+        .bin_name(bin)
+        .setting(AppSettings::ColorNever)
+        .setting(AppSettings::NextLineHelp)
+        .set_term_width(0);
+
+    let mut page = String::new();
+
+    page.push_str(&format!(".TH {} 1 \"\" \"mmv {}\"\n", escape(&title.to_uppercase()), env!("CARGO_PKG_VERSION")));
+
+    page.push_str(".SH NAME\n");
+    page.push_str(&format!("{} \\- {}\n", escape(&name), escape(&help(&app, "{about}")?)));
+
+    page.push_str(".SH SYNOPSIS\n");
+    page.push_str(&format!("\\fB{}\\fR\n", escape(&help(&app, "{usage}")?)));
+
+    // Without a long description, clap renders a placeholder
+    let about = help(&app, "{long-about}")?;
+    if about != "unknown about" {
+        page.push_str(".SH DESCRIPTION\n");
+        page.push_str(&paragraphs(&about));
+    }
+
+    let positionals = help(&app, "{positionals}")?;
+    if !positionals.trim().is_empty() {
+        page.push_str(".SH ARGUMENTS\n");
+        page.push_str(&args(&positionals));
+    }
+
+    page.push_str(".SH OPTIONS\n");
+    page.push_str(&args(&help(&app, "{unified}")?));
+
+    if subcommand.is_none() {
+        page.push_str(".SH COMMANDS\n");
+        for subcommand in crate::subcommands() {
+            let name = subcommand.get_name().to_string();
+            page.push_str(&format!(".TP\n\\fB{}\\fR\n{}\n", escape(&name), escape(&help(&subcommand, "{about}")?)));
+            page.push_str(&format!("See \\fBmmv\\-{}\\fR(1).\n", escape(&name)));
+        }
+    } else {
+        page.push_str(".SH SEE ALSO\n");
+        page.push_str("\\fBmmv\\fR(1)\n");
+    }
+
+    return Ok(page);
+}
+
+/// Renders the help of the command using the given template.
+fn help(app: &App<'static, 'static>, template: &'static str) -> Result<String> {
+    let mut output = Vec::new();
+    app.clone()
+        .template(template)
+        .write_long_help(&mut output)?;
+
+    return Ok(String::from_utf8(output)?.trim_end().to_string());
+}
+
+/// Converts the arguments rendered by clap to a tagged paragraph per argument.
+///
+/// Each argument starts with its flags on a separate line followed by the help indented further.
+fn args(help: &str) -> String {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+
+    let mut roff = String::new();
+    let mut heading = None;
+
+    for line in help.lines().filter(|line| !line.trim().is_empty()) {
+        match heading {
+            Some(heading) if indent(line) > heading => {
+                roff.push_str(&escape(line.trim()));
+                roff.push('\n');
+            }
+            _ => {
+                heading = Some(indent(line));
+                roff.push_str(&format!(".TP\n\\fB{}\\fR\n", escape(line.trim())));
+            }
+        }
+    }
+
+    return roff;
+}
+
+/// Converts text with blank lines between paragraphs.
+fn paragraphs(text: &str) -> String {
+    return text.split("\n\n")
+        .map(|paragraph| format!(".PP\n{}\n", escape(paragraph.trim())))
+        .collect();
+}
+
+/// Escapes text for roff.
+fn escape(text: &str) -> String {
+    return text.lines()
+        .map(|line| {
+            let line = line.replace('\\', "\\e").replace('-', "\\-");
+            if line.starts_with('.') || line.starts_with('\'') {
+                return format!("\\&{}", line);
+            }
+            return line;
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("man")
+        .about("Prints the man page of mmv or one of its commands")
+        .arg(Arg::with_name("command")
+            .value_name("COMMAND")
+            .help("The command to print the man page for")
+            .index(1))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("DIR")
+            .help("Write the man pages of mmv and all commands to the directory")
+            .takes_value(true)
+            .conflicts_with("command"));
+}
@@ -1,5 +1,26 @@
 pub mod init;
 pub mod update;
 pub mod status;
+pub mod check;
+pub mod list;
+pub mod grep;
 pub mod edit;
 pub mod execute;
+pub mod resume;
+pub mod ignore;
+pub mod undo;
+pub mod sub;
+pub mod template;
+pub mod renumber;
+pub mod transform;
+pub mod dedupe;
+pub mod diff_dirs;
+pub mod set;
+pub mod rm;
+pub mod keep;
+pub mod import;
+pub mod reverse;
+pub mod abort;
+pub mod stash;
+pub mod completions;
+pub mod man;
@@ -0,0 +1,103 @@
+use std::path::Path;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use globset::Glob;
+use yansi::Paint;
+
+use crate::changeset::{Action, Workspace};
+use crate::escape::unescape;
+use crate::template::{PathVariables, Template};
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let pattern = matches.value_of("pattern").expect("No pattern")
+        .parse::<Template>()?;
+
+    let filter = matches.value_of("filter")
+        .map(|filter| Glob::new(filter).map(|glob| glob.compile_matcher()))
+        .transpose()
+        .map_err(anyhow::Error::from)?;
+
+    let start = matches.value_of("start").expect("No start")
+        .parse::<usize>()
+        .map_err(anyhow::Error::from)?;
+
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    let changeset = workspace.import()?;
+    let mut changeset = changeset.clean()
+        .ok_or_else(|| ProgramError::NotClean)?;
+
+    // The records are numbered in the order of the change set files
+    let sources = changeset.ordered().into_iter()
+        .map(|(_, source, _)| source.clone())
+        .collect::<Vec<_>>();
+
+    let mut counter = start;
+    for source in sources {
+        if let Some(filter) = filter.as_ref() {
+            if !filter.is_match(&source) {
+                continue;
+            }
+        }
+
+        let action = changeset.records_mut().get_mut(&source).expect("No record");
+
+        // The pattern replaces the file name of the current target, which is the source itself
+        // for ignored records
+        let current = match action {
+            Action::Move(target) => target.clone(),
+            Action::Ignore(_) => source.clone(),
+            Action::Delete => continue,
+        };
+
+        let name = unescape(&pattern.render(&PathVariables::new(&current, counter))?);
+        let target = match current.parent() {
+            Some(parent) => parent.join(name),
+            None => name,
+        };
+
+        info!("{} {} {} {}", Paint::yellow("#").bold(), source.display(), Paint::yellow("→"), target.display());
+        *action = Action::Move(target);
+
+        counter += 1;
+    }
+
+    if !matches.is_present("dry-run") {
+        changeset.export()?;
+    }
+
+    return Ok(());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("renumber")
+        .about("Renames the targets to a numbered pattern in the order of the change set")
+        .after_help("The pattern replaces the file name of the current target and supports the path placeholders \
+            of templates, i.e. {counter:03}, {name} and {ext}, which refer to the current target.")
+        .arg(Arg::with_name("pattern")
+            .long("pattern")
+            .short("p")
+            .value_name("PATTERN")
+            .help("The pattern for the target file names")
+            .required(true)
+            .takes_value(true))
+        .arg(Arg::with_name("filter")
+            .short("f")
+            .long("filter")
+            .value_name("GLOB")
+            .help("Only renumber records with a source matching the pattern")
+            .takes_value(true))
+        .arg(Arg::with_name("start")
+            .long("start")
+            .value_name("N")
+            .help("The first number")
+            .takes_value(true)
+            .default_value("1"))
+        .arg(Arg::with_name("dry-run")
+            .short("n")
+            .long("dry-run")
+            .takes_value(false)
+            .help("Print the renumbered targets without updating the change set"));
+}
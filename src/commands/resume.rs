@@ -0,0 +1,115 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::anyhow;
+use clap::{App, ArgMatches, SubCommand};
+use yansi::Paint;
+
+use crate::changeset::{Action, Workspace};
+use crate::commands::execute;
+use crate::expand::expand;
+use crate::journal::{Journal, JournalEntry};
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    if !workspace.journal_path().is_file() {
+        eprintln!("{} Use mmv execute to start an execution", Paint::red("No journal."));
+        return Ok(());
+    }
+
+    let target = Journal::target(&workspace)?
+        .ok_or_else(|| anyhow!("No target directory in journal"))?;
+
+    let changeset = workspace.import()?;
+    let mut changeset = changeset.clean()
+        .ok_or_else(|| ProgramError::NotClean)?;
+
+    // The sources of all actions recorded as completed. Copied or linked sources are kept in
+    // place, so their records stay in the change set
+    let mut applied = BTreeSet::new();
+    let mut kept_sources = BTreeSet::new();
+    for entry in Journal::load(changeset.workspace())? {
+        match entry {
+            JournalEntry::Move { source, .. } => applied.insert(source),
+            JournalEntry::Link { source, .. } | JournalEntry::Copy { source, .. } => kept_sources.insert(source),
+            JournalEntry::Delete { source } => applied.insert(source),
+            JournalEntry::Symlink { .. } => false,
+        };
+    }
+
+    let mut journal = None;
+    let mut skipped = Vec::new();
+    let mut kept = Vec::new();
+    for (record, action) in changeset.records().iter() {
+        let source = std::path::absolute(changeset.path().join(record))
+            .map_err(anyhow::Error::from)?;
+
+        if applied.contains(&source) {
+            skipped.push(record.clone());
+            continue;
+        }
+
+        if kept_sources.contains(&source) {
+            kept.push(record.clone());
+            continue;
+        }
+
+        // Actions completed without being recorded, as the execution was aborted in between, are
+        // detected by the missing source and recorded now
+        if source.symlink_metadata().is_ok() {
+            continue;
+        }
+
+        let entry = match action {
+            Action::Move(path) => {
+                let path = if matches.is_present("expand") { expand(path)? } else { path.clone() };
+                if target.join(&path).symlink_metadata().is_err() {
+                    continue;
+                }
+
+                JournalEntry::moved(&source, target.join(path))?
+            }
+            Action::Delete => JournalEntry::deleted(&source)?,
+            Action::Ignore(_) => continue,
+        };
+
+        if !matches.is_present("dry-run") {
+            if journal.is_none() {
+                journal = Some(Journal::append(changeset.workspace())?);
+            }
+
+            journal.as_mut().expect("No journal").record(entry)?;
+        }
+
+        skipped.push(record.clone());
+    }
+
+    for record in skipped.iter() {
+        info!("{} {} {}", Paint::new("↷").dimmed(), record.display(), Paint::new("already applied").dimmed());
+        changeset.records_mut().remove(record);
+    }
+
+    drop(journal);
+
+    if !matches.is_present("dry-run") {
+        changeset.export()?;
+    }
+
+    // Copied or linked records are kept in the workspace but not executed again
+    for record in kept.iter() {
+        info!("{} {} {}", Paint::new("↷").dimmed(), record.display(), Paint::new("already applied").dimmed());
+        changeset.records_mut().remove(record);
+    }
+
+    return execute::execute_changeset(changeset, &target, matches, true);
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("resume")
+        .about("Continues an aborted execution to the same target directory")
+        .after_help("Records already applied according to the journal or the file system are skipped.")
+        .args(&execute::args());
+}
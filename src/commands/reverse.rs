@@ -0,0 +1,59 @@
+use std::path::Path;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use yansi::Paint;
+
+use crate::changeset::{Action, ChangeSet, Workspace};
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let target = Workspace::at(matches.value_of("target").expect("No target"));
+
+    if !matches.is_present("force") && target.is_initialized() {
+        eprintln!("{} Use -f to reset", Paint::red("Target already initialized."));
+        return Ok(());
+    }
+
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    let changeset = workspace.import()?;
+    let changeset = changeset.clean()
+        .ok_or_else(|| ProgramError::NotClean)?;
+
+    // Only moved files end up in the target directory, so all other records are dropped
+    let records = changeset.records().iter()
+        .filter_map(|(source, action)| match action {
+            Action::Move(target) => Some((target.clone(), Action::Move(source.clone()))),
+            Action::Delete | Action::Ignore(_) => None,
+        })
+        .collect();
+
+    let target = target.convert(changeset.workspace().format())?;
+
+    let reversed = ChangeSet::create(target, records);
+    reversed.export()?;
+
+    info!("{} {} records in {}",
+             Paint::green("Reversed"),
+             reversed.records().len(),
+             reversed.path().display());
+    info!("Use mmv -s {} execute {} to move the files back", reversed.path().display(), changeset.path().display());
+
+    return Ok(());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("reverse")
+        .about("Creates a workspace in the target directory moving all moved files back")
+        .arg(Arg::with_name("target")
+            .value_name("DIR")
+            .help("The target directory the change set is executed to")
+            .required(true)
+            .index(1))
+        .arg(Arg::with_name("force")
+            .short("f")
+            .long("force")
+            .takes_value(false)
+            .help("Replace the change set in the target directory if already initialized"));
+}
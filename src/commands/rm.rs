@@ -0,0 +1,44 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use yansi::Paint;
+
+use crate::changeset::{Action, Workspace};
+use crate::escape::unescape;
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    let changeset = workspace.import()?;
+    let mut changeset = changeset.clean()
+        .ok_or_else(|| ProgramError::NotClean)?;
+
+    for source in matches.values_of("sources").expect("No sources") {
+        let source = unescape(source);
+        let source = source.strip_prefix(".").unwrap_or(&source).to_path_buf();
+
+        let action = changeset.records_mut().get_mut(&source)
+            .ok_or_else(|| anyhow!("Unknown source: {}", source.display()))?;
+
+        info!("{} {}", Paint::red("✕").bold(), source.display());
+        *action = Action::Delete;
+    }
+
+    changeset.export()?;
+
+    return Ok(());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("rm")
+        .about("Marks records for deletion")
+        .arg(Arg::with_name("sources")
+            .value_name("SOURCE")
+            .help("The sources of the records")
+            .required(true)
+            .multiple(true)
+            .index(1));
+}
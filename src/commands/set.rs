@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use yansi::Paint;
+
+use crate::changeset::{Action, Workspace};
+use crate::escape::unescape;
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let source = unescape(matches.value_of("source").expect("No source"));
+    let source = source.strip_prefix(".").unwrap_or(&source).to_path_buf();
+
+    let target = unescape(matches.value_of("target").expect("No target"));
+
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    let changeset = workspace.import()?;
+    let mut changeset = changeset.clean()
+        .ok_or_else(|| ProgramError::NotClean)?;
+
+    let action = changeset.records_mut().get_mut(&source)
+        .ok_or_else(|| anyhow!("Unknown source: {}", source.display()))?;
+
+    info!("{} {} {} {}", Paint::yellow("~").bold(), source.display(), Paint::yellow("→"), target.display());
+    *action = Action::Move(target);
+
+    changeset.export()?;
+
+    return Ok(());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("set")
+        .about("Sets the target of a single record")
+        .arg(Arg::with_name("source")
+            .value_name("SOURCE")
+            .help("The source of the record")
+            .required(true)
+            .index(1))
+        .arg(Arg::with_name("target")
+            .value_name("TARGET")
+            .help("The target to move the source to")
+            .required(true)
+            .index(2));
+}
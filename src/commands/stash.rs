@@ -0,0 +1,97 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use yansi::Paint;
+
+use crate::changeset::{validate_name, Workspace};
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    match matches.subcommand() {
+        ("save", Some(matches)) => {
+            let name = matches.value_of("name").expect("No name");
+            validate_name(name)?;
+
+            let current = Workspace::open(workspace)
+                .ok_or_else(|| ProgramError::NotInitialized)?;
+
+            let stash = Workspace::named(workspace, name);
+            if stash.is_initialized() && !matches.is_present("force") {
+                eprintln!("{} Use -f to replace it", Paint::red(format!("Change set {} exists already.", name)));
+                return Ok(());
+            }
+
+            current.copy_to(&stash)?;
+
+            info!("{} {}", Paint::green("Saved change set as"), name);
+        }
+
+        ("apply", Some(matches)) => {
+            let name = matches.value_of("name").expect("No name");
+            validate_name(name)?;
+
+            let stash = Workspace::named(workspace, name);
+            if !stash.is_initialized() {
+                return Err(anyhow!("Unknown change set: {}", name).into());
+            }
+
+            stash.copy_to(&Workspace::selected(workspace))?;
+
+            info!("{} {}", Paint::green("Applied change set"), name);
+        }
+
+        ("drop", Some(matches)) => {
+            let name = matches.value_of("name").expect("No name");
+            validate_name(name)?;
+
+            let stash = Workspace::named(workspace, name);
+            if !stash.is_initialized() {
+                return Err(anyhow!("Unknown change set: {}", name).into());
+            }
+
+            stash.remove()?;
+
+            info!("{} {}", Paint::green("Dropped change set"), name);
+        }
+
+        ("list", Some(_)) => {
+            for name in Workspace::names(workspace)? {
+                println!("{}", name);
+            }
+        }
+
+        _ => unreachable!()
+    }
+
+    return Ok(());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    let name = || Arg::with_name("name")
+        .value_name("NAME")
+        .help("The name of the change set")
+        .required(true)
+        .index(1);
+
+    return SubCommand::with_name("stash")
+        .about("Maintains named change sets, which can be selected using --changeset")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("save")
+            .about("Saves a copy of the current change set under a name")
+            .arg(name())
+            .arg(Arg::with_name("force")
+                .short("f")
+                .long("force")
+                .takes_value(false)
+                .help("Replace an existing change set of the same name")))
+        .subcommand(SubCommand::with_name("apply")
+            .about("Replaces the current change set by a copy of the named one")
+            .arg(name()))
+        .subcommand(SubCommand::with_name("drop")
+            .about("Removes a named change set")
+            .arg(name()))
+        .subcommand(SubCommand::with_name("list")
+            .about("Lists all named change sets")
+            .alias("ls"));
+}
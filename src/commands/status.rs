@@ -1,9 +1,10 @@
 use std::path::Path;
 
-use clap::{App, ArgMatches, SubCommand};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use yansi::Paint;
 
-use crate::changeset::Workspace;
+use crate::changeset::{Action, ChangeSetImport, Format, Workspace};
+use crate::escape::escape;
 use crate::ProgramError;
 
 pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
@@ -12,10 +13,102 @@ pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
 
     let changeset = workspace.import()?;
 
+    if matches.is_present("porcelain") {
+        return porcelain(&changeset);
+    }
+
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&changeset.to_json()?).map_err(anyhow::Error::from)?);
+        return Ok(());
+    }
+
     if changeset.is_clean() {
         println!("{}", Paint::green("Workspace is clean"));
     } else {
         println!("{}", Paint::red("Workspace is not clean").bold());
+        explain(&changeset);
+    }
+
+    let moves = changeset.records().values()
+        .filter(|action| matches!(action, Action::Move(_)))
+        .count();
+    let deletes = changeset.records().values()
+        .filter(|action| matches!(action, Action::Delete))
+        .count();
+    let ignores = changeset.records().values()
+        .filter(|action| matches!(action, Action::Ignore(_)))
+        .count();
+
+    println!("{} to move, {} to delete, {} ignored",
+             Paint::cyan(moves).bold(),
+             Paint::red(deletes).bold(),
+             Paint::new(ignores).bold());
+
+    if matches.is_present("short") {
+        return Ok(());
+    }
+
+    if moves + deletes > 0 {
+        println!();
+    }
+
+    for (source, action) in changeset.records().iter() {
+        match action {
+            Action::Move(target) => {
+                println!("{} {} {} {}", Paint::cyan("➤").bold(), source.display(), Paint::cyan("→"), target.display());
+            }
+            Action::Delete => {
+                println!("{} {}", Paint::red("✕").bold(), source.display());
+            }
+            Action::Ignore(_) => {}
+        }
+    }
+
+    return Ok(());
+}
+
+/// Prints the lines which prevent the workspace from being clean.
+fn explain(changeset: &ChangeSetImport) {
+    let (sources_file, targets_file) = match changeset.workspace().format() {
+        Format::Split => (changeset.workspace().sources_path(), changeset.workspace().targets_path()),
+        Format::Combined => (changeset.workspace().changes_path(), changeset.workspace().changes_path()),
+    };
+
+    if !changeset.unmapped_sources().is_empty() {
+        println!("Sources without a target:");
+        for (line, source) in changeset.unmapped_sources().iter() {
+            println!("  {}:{}: {}", sources_file.display(), Paint::red(line), source.display());
+        }
+    }
+
+    if !changeset.unmapped_targets().is_empty() {
+        println!("Targets without a source:");
+        for (line, target) in changeset.unmapped_targets().iter() {
+            println!("  {}:{}: {}", targets_file.display(), Paint::red(line), target);
+        }
+    }
+
+    println!();
+}
+
+/// Prints the status in a stable, machine-readable format.
+///
+/// Each record is printed on a line of tab-separated fields starting with the action code.
+fn porcelain(changeset: &ChangeSetImport) -> Result<(), ProgramError> {
+    for (source, action) in changeset.records().iter() {
+        match action {
+            Action::Move(target) => println!("M\t{}\t{}", escape(source), escape(target)),
+            Action::Delete => println!("D\t{}", escape(source)),
+            Action::Ignore(comment) => println!("I\t{}\t{}", escape(source), comment),
+        }
+    }
+
+    for (_, source) in changeset.unmapped_sources().iter() {
+        println!("S\t{}", escape(source));
+    }
+
+    for (_, target) in changeset.unmapped_targets().iter() {
+        println!("T\t{}", target);
     }
 
     return Ok(());
@@ -23,5 +116,27 @@ pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
 
 pub fn subcommand() -> App<'static, 'static> {
     return SubCommand::with_name("status")
-        .about("Prints the current status");
-}
\ No newline at end of file
+        .about("Prints the current status")
+        .arg(Arg::with_name("short")
+            .long("short")
+            .takes_value(false)
+            .help("Only print the number of pending actions"))
+        .arg(Arg::with_name("porcelain")
+            .long("porcelain")
+            .takes_value(false)
+            .conflicts_with("short")
+            .help("Print the records in a stable machine-readable format"))
+        .arg(Arg::with_name("json")
+            .long("json")
+            .takes_value(false)
+            .conflicts_with_all(&["short", "porcelain"])
+            .help("Print the full change set as JSON"))
+        .after_help("PORCELAIN FORMAT:\n    \
+            Each line consists of tab-separated fields starting with one of the following codes:\n    \
+            M <source> <target>   The source is moved to the target\n    \
+            D <source>            The source is deleted\n    \
+            I <source> <comment>  The source is ignored\n    \
+            S <source>            The source has no target assigned\n    \
+            T <target>            The target line has no source assigned\n\n    \
+            The format does not change between releases and is never colored.");
+}
@@ -0,0 +1,191 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use globset::Glob;
+use regex::{NoExpand, Regex, RegexBuilder};
+use yansi::Paint;
+
+use crate::changeset::{Action, Workspace};
+use crate::escape::{escape, unescape};
+use crate::ProgramError;
+
+/// A sed-style substitution expression (`s/pattern/replacement/flags`).
+struct Substitution {
+    pattern: Regex,
+    replacement: Replacement,
+    global: bool,
+}
+
+/// The replacement of a substitution.
+enum Replacement {
+    /// Used as it is, without any references to groups
+    Literal(String),
+
+    /// Referring to groups of the pattern using the regex crate syntax
+    Expand(String),
+}
+
+impl std::str::FromStr for Substitution {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        if chars.next() != Some('s') {
+            return Err(anyhow!("Expression must start with 's': {}", s));
+        }
+
+        let delimiter = chars.next()
+            .ok_or_else(|| anyhow!("Missing delimiter: {}", s))?;
+
+        // Split the remaining expression by the delimiter while respecting escaped delimiters
+        let mut parts = vec![String::new()];
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                match chars.next() {
+                    Some(c) if c == delimiter => parts.last_mut().expect("No part").push(c),
+                    Some(c) => {
+                        let part = parts.last_mut().expect("No part");
+                        part.push('\\');
+                        part.push(c);
+                    }
+                    None => return Err(anyhow!("Trailing escape: {}", s)),
+                }
+            } else if c == delimiter {
+                parts.push(String::new());
+            } else {
+                parts.last_mut().expect("No part").push(c);
+            }
+        }
+
+        if parts.len() != 3 {
+            return Err(anyhow!("Expression must have the form s/pattern/replacement/flags: {}", s));
+        }
+
+        let flags = parts.pop().expect("No flags");
+        let replacement = parts.pop().expect("No replacement");
+        let pattern = parts.pop().expect("No pattern");
+
+        let mut global = false;
+        let mut case_insensitive = false;
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'i' => case_insensitive = true,
+                _ => return Err(anyhow!("Unknown flag '{}': {}", flag, s)),
+            }
+        }
+
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .build()?;
+
+        // Translate sed-style back references (\1) to the regex crate syntax (${1}), other dollar
+        // signs are taken literally
+        let references = Regex::new(r"\\([0-9])").expect("Invalid regex");
+        let replacement = if references.is_match(&replacement) {
+            Replacement::Expand(references.replace_all(&replacement.replace('$', "$$"), "$${$1}").into_owned())
+        } else {
+            Replacement::Literal(replacement)
+        };
+
+        return Ok(Self {
+            pattern,
+            replacement,
+            global,
+        });
+    }
+}
+
+impl Substitution {
+    /// Applies the substitution to the escaped form of the path.
+    fn apply(&self, path: &Path) -> Option<PathBuf> {
+        let path = escape(path);
+        let path = path.as_str();
+
+        // A limit of zero replaces all matches
+        let limit = if self.global { 0 } else { 1 };
+
+        let result = match &self.replacement {
+            Replacement::Literal(replacement) => self.pattern.replacen(path, limit, NoExpand(replacement)),
+            Replacement::Expand(replacement) => self.pattern.replacen(path, limit, replacement.as_str()),
+        };
+
+        if result == path {
+            return None;
+        }
+
+        return Some(unescape(&result));
+    }
+}
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let substitution = matches.value_of("expression").expect("No expression")
+        .parse::<Substitution>()?;
+
+    let filter = matches.value_of("filter")
+        .map(|filter| Glob::new(filter).map(|glob| glob.compile_matcher()))
+        .transpose()
+        .map_err(anyhow::Error::from)?;
+
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    let changeset = workspace.import()?;
+    let mut changeset = changeset.clean()
+        .ok_or_else(|| ProgramError::NotClean)?;
+
+    let all = matches.is_present("all");
+
+    for (source, action) in changeset.records_mut().iter_mut() {
+        if let Some(filter) = filter.as_ref() {
+            if !filter.is_match(source) {
+                continue;
+            }
+        }
+
+        // Ignored records are substituted based on their source path if requested
+        let target = match action {
+            Action::Move(target) => target.as_path(),
+            Action::Ignore(_) if all => source.as_path(),
+            _ => continue,
+        };
+
+        if let Some(target) = substitution.apply(target) {
+            info!("{} {} {} {}", Paint::yellow("~").bold(), source.display(), Paint::yellow("→"), target.display());
+            *action = Action::Move(target);
+        }
+    }
+
+    if !matches.is_present("dry-run") {
+        changeset.export()?;
+    }
+
+    return Ok(());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("sub")
+        .about("Applies a regular expression substitution to the targets")
+        .arg(Arg::with_name("expression")
+            .value_name("EXPRESSION")
+            .help("The substitution in the form s/pattern/replacement/flags using extended regex syntax")
+            .required(true)
+            .index(1))
+        .arg(Arg::with_name("filter")
+            .short("f")
+            .long("filter")
+            .value_name("GLOB")
+            .help("Only substitute records with a source matching the pattern")
+            .takes_value(true))
+        .arg(Arg::with_name("all")
+            .short("a")
+            .long("all")
+            .takes_value(false)
+            .help("Substitute the source path of ignored records, too"))
+        .arg(Arg::with_name("dry-run")
+            .short("n")
+            .long("dry-run")
+            .takes_value(false)
+            .help("Print the substitutions without updating the change set"));
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use globset::Glob;
+use yansi::Paint;
+
+use crate::changeset::{Action, Workspace};
+use crate::escape::unescape;
+use crate::metadata::MetadataVariables;
+use crate::template::{Chain, PathVariables, Template};
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let template = matches.value_of("template").expect("No template")
+        .parse::<Template>()?;
+
+    let filter = matches.value_of("filter")
+        .map(|filter| Glob::new(filter).map(|glob| glob.compile_matcher()))
+        .transpose()
+        .map_err(anyhow::Error::from)?;
+
+    let start = matches.value_of("start").expect("No start")
+        .parse::<usize>()
+        .map_err(anyhow::Error::from)?;
+
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    let changeset = workspace.import()?;
+    let mut changeset = changeset.clean()
+        .ok_or_else(|| ProgramError::NotClean)?;
+
+    let root = changeset.path().to_path_buf();
+
+    // The records are numbered in the order of the change set files
+    let sources = changeset.ordered().into_iter()
+        .map(|(_, source, _)| source.clone())
+        .collect::<Vec<_>>();
+
+    let mut counter = start;
+    for source in sources {
+        if let Some(filter) = filter.as_ref() {
+            if !filter.is_match(&source) {
+                continue;
+            }
+        }
+
+        let action = changeset.records_mut().get_mut(&source).expect("No record");
+
+        // Files marked for deletion are left alone
+        if let Action::Delete = action {
+            continue;
+        }
+
+        let path = root.join(&source);
+
+        let path_variables = PathVariables::new(&source, counter);
+        let metadata_variables = MetadataVariables::new(&path);
+        let variables = Chain::new(vec![&path_variables, &metadata_variables]);
+
+        let target = unescape(&template.render(&variables)?);
+
+        info!("{} {} {} {}", Paint::yellow("~").bold(), source.display(), Paint::yellow("→"), target.display());
+        *action = Action::Move(target);
+
+        counter += 1;
+    }
+
+    if !matches.is_present("dry-run") {
+        changeset.export()?;
+    }
+
+    return Ok(());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("template")
+        .about("Generates the targets from a template")
+        .after_help("PLACEHOLDERS:\n    \
+            {path}        The path of the source file\n    \
+            {name}        The file name without extension\n    \
+            {ext}         The file extension\n    \
+            {filename}    The file name including the extension\n    \
+            {parent}      The directory containing the source file\n    \
+            {counter:03}  A counter incremented for each record, optionally zero padded\n    \
+            {mtime}       The modification time, formatted like {mtime:%Y-%m-%d}\n    \
+            {exif.date}   The capture time of a photo, formatted like {exif.date:%Y/%m}\n    \
+            {exif.make}   The camera manufacturer of a photo\n    \
+            {exif.model}  The camera model of a photo\n    \
+            {artist}      The artist of an audio file\n    \
+            {album}       The album of an audio file\n    \
+            {title}       The title of an audio file\n    \
+            {track:02}    The track number of an audio file, optionally zero padded\n    \
+            {year}        The release year of an audio file\n    \
+            {genre}       The genre of an audio file")
+        .arg(Arg::with_name("template")
+            .value_name("TEMPLATE")
+            .help("The template for the target paths")
+            .required(true)
+            .index(1))
+        .arg(Arg::with_name("filter")
+            .short("f")
+            .long("filter")
+            .value_name("GLOB")
+            .help("Only update records with a source matching the pattern")
+            .takes_value(true))
+        .arg(Arg::with_name("start")
+            .long("start")
+            .value_name("N")
+            .help("The first value of the counter")
+            .takes_value(true)
+            .default_value("1"))
+        .arg(Arg::with_name("dry-run")
+            .short("n")
+            .long("dry-run")
+            .takes_value(false)
+            .help("Print the generated targets without updating the change set"));
+}
@@ -0,0 +1,131 @@
+use std::ffi::OsStr;
+use std::path::Path;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use globset::Glob;
+use yansi::Paint;
+
+use crate::changeset::{is_dir, Action, Workspace};
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
+    let case = matches.value_of("case").expect("No case");
+    let stem_only = matches.is_present("stem");
+
+    let filter = matches.value_of("filter")
+        .map(|filter| Glob::new(filter).map(|glob| glob.compile_matcher()))
+        .transpose()
+        .map_err(anyhow::Error::from)?;
+
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    let changeset = workspace.import()?;
+    let mut changeset = changeset.clean()
+        .ok_or_else(|| ProgramError::NotClean)?;
+
+    let mut count = 0;
+    for (source, action) in changeset.records_mut().iter_mut() {
+        if let Some(filter) = filter.as_ref() {
+            if !filter.is_match(source) {
+                continue;
+            }
+        }
+
+        // The file name of the current target is transformed, which is the source itself for
+        // ignored records
+        let current = match action {
+            Action::Move(target) => target.clone(),
+            Action::Ignore(_) => source.clone(),
+            Action::Delete => continue,
+        };
+
+        let name = match current.file_name().and_then(OsStr::to_str) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        // Hidden files have no extension, but a leading dot
+        let (stem, ext) = match name.rfind('.') {
+            Some(i) if stem_only && i > 0 => name.split_at(i),
+            _ => (name, ""),
+        };
+
+        let transformed = format!("{}{}", transform(case, stem), ext);
+        if transformed == name {
+            continue;
+        }
+
+        // Directories keep their trailing slash
+        let mut target = current.with_file_name(transformed);
+        if is_dir(&current) {
+            target.as_mut_os_string().push("/");
+        }
+
+        info!("{} {} {} {}", Paint::yellow("~").bold(), source.display(), Paint::yellow("→"), target.display());
+        *action = Action::Move(target);
+
+        count += 1;
+    }
+
+    info!("{} targets transformed", count);
+
+    if !matches.is_present("dry-run") {
+        changeset.export()?;
+    }
+
+    return Ok(());
+}
+
+/// Converts a name to lower, upper or title case.
+///
+/// Title case starts each word with an uppercase letter followed by lowercase letters, where
+/// words are separated by anything but letters, digits and apostrophes.
+fn transform(case: &str, name: &str) -> String {
+    return match case {
+        "lower" => name.to_lowercase(),
+        "upper" => name.to_uppercase(),
+        "title" => {
+            let mut result = String::with_capacity(name.len());
+            let mut start = true;
+            for c in name.chars() {
+                if start {
+                    result.extend(c.to_uppercase());
+                } else {
+                    result.extend(c.to_lowercase());
+                }
+
+                start = !(c.is_alphanumeric() || c == '\'');
+            }
+
+            result
+        }
+        _ => unreachable!(),
+    };
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("transform")
+        .about("Changes the case of the target file names")
+        .arg(Arg::with_name("case")
+            .value_name("CASE")
+            .help("The case to convert the file names to")
+            .possible_values(&["lower", "upper", "title"])
+            .required(true)
+            .index(1))
+        .arg(Arg::with_name("filter")
+            .short("f")
+            .long("filter")
+            .value_name("GLOB")
+            .help("Only transform records with a source matching the pattern")
+            .takes_value(true))
+        .arg(Arg::with_name("stem")
+            .long("stem")
+            .takes_value(false)
+            .help("Only transform the name without the extension, keeping the case of the extension"))
+        .arg(Arg::with_name("dry-run")
+            .short("n")
+            .long("dry-run")
+            .takes_value(false)
+            .help("Print the transformed targets without updating the change set"));
+}
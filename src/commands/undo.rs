@@ -0,0 +1,152 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::anyhow;
+use clap::{App, ArgMatches, SubCommand};
+use yansi::Paint;
+
+use crate::changeset::Workspace;
+use crate::commands::execute;
+use crate::journal::{Journal, JournalEntry};
+use crate::scan::is_special;
+use crate::verbosity::{self, Verbosity};
+use crate::ProgramError;
+
+pub fn run(workspace: &Path, _matches: &ArgMatches) -> Result<(), ProgramError> {
+    let workspace = Workspace::open(workspace)
+        .ok_or_else(|| ProgramError::NotInitialized)?;
+
+    if !workspace.journal_path().is_file() {
+        eprintln!("{} Nothing to undo", Paint::red("No journal."));
+        return Ok(());
+    }
+
+    let journal = Journal::load(&workspace)?;
+
+    // Files created at the sources after the execution are never overwritten. Paths which are
+    // cleared by undoing a later entry first, like the temporary names of cycles or the symlinks
+    // left behind, are free by then
+    let mut cleared = BTreeSet::new();
+    for entry in journal.iter().rev() {
+        match entry {
+            JournalEntry::Move { source, target } => {
+                if !cleared.contains(source) && source.symlink_metadata().is_ok() {
+                    return Err(anyhow!("{}: exists, not moving {} back over it", source.display(), target.display()).into());
+                }
+
+                cleared.remove(source);
+                cleared.insert(target.clone());
+            }
+            JournalEntry::Link { target, .. } | JournalEntry::Copy { target, .. } => {
+                cleared.insert(target.clone());
+            }
+            JournalEntry::Symlink { path, .. } => {
+                cleared.insert(path.clone());
+            }
+            JournalEntry::Delete { .. } => {}
+        }
+    }
+
+    // Replay the journal backwards to restore the state before the execution
+    for entry in journal.iter().rev() {
+        match entry {
+            JournalEntry::Move { source, target } => {
+                if verbosity::enabled(Verbosity::Normal) {
+                    print!("{} {} ", Paint::cyan("➤").bold(), source.display());
+                }
+
+                if let Some(parent) = source.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(anyhow::Error::from)?;
+                }
+
+                if target.symlink_metadata().is_ok_and(|metadata| metadata.is_symlink()) {
+                    // Symlinks are recreated to adjust relative links to the old location
+                    execute::copy_symlink(target, source, source)?;
+
+                    std::fs::remove_file(target)
+                        .map_err(anyhow::Error::from)?;
+                } else if target.symlink_metadata().is_ok_and(|metadata| is_special(metadata.file_type())) {
+                    // FIFOs are recreated, as reading them would block
+                    execute::copy_special(target, source)?;
+
+                    std::fs::remove_file(target)
+                        .map_err(anyhow::Error::from)?;
+                } else if is_dir(target) {
+                    // Directories are renamed back as a whole if possible
+                    match std::fs::rename(target, source) {
+                        Ok(()) => {}
+                        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+                            execute::copy_dir(target, source, &|target, source| {
+                                reflink::reflink_or_copy(target, source)?;
+                                return Ok(());
+                            })?;
+
+                            std::fs::remove_dir_all(target)
+                                .map_err(anyhow::Error::from)?;
+                        }
+                        Err(err) => return Err(anyhow::Error::from(err).into()),
+                    }
+                } else {
+                    // Files are renamed back, keeping their attributes, unless moved across devices
+                    match std::fs::rename(target, source) {
+                        Ok(()) => {}
+                        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+                            reflink::reflink_or_copy(target, source)
+                                .map_err(anyhow::Error::from)?;
+
+                            std::fs::remove_file(target)
+                                .map_err(anyhow::Error::from)?;
+                        }
+                        Err(err) => return Err(anyhow::Error::from(err).into()),
+                    }
+                }
+
+                info!("{}", Paint::green("✓").bold());
+            }
+
+            JournalEntry::Link { target, .. } | JournalEntry::Copy { target, .. } => {
+                // The source has been left in place
+                if verbosity::enabled(Verbosity::Normal) {
+                    print!("{} {} ", Paint::red("✕").bold(), target.display());
+                }
+
+                if is_dir(target) {
+                    std::fs::remove_dir_all(target)
+                        .map_err(anyhow::Error::from)?;
+                } else {
+                    std::fs::remove_file(target)
+                        .map_err(anyhow::Error::from)?;
+                }
+
+                info!("{}", Paint::green("✓").bold());
+            }
+
+            JournalEntry::Symlink { path, .. } => {
+                // Remove the symlink left behind before the file is moved back
+                std::fs::remove_file(path)
+                    .map_err(anyhow::Error::from)?;
+            }
+
+            JournalEntry::Delete { source } => {
+                // Deleted files are gone for good
+                warn!("{}: not restorable", source.display());
+            }
+        }
+    }
+
+    std::fs::remove_file(workspace.journal_path())
+        .map_err(anyhow::Error::from)?;
+
+    return Ok(());
+}
+
+/// Checks if a path is a directory without following symlinks.
+fn is_dir(path: &Path) -> bool {
+    return path.symlink_metadata().is_ok_and(|metadata| metadata.is_dir());
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    return SubCommand::with_name("undo")
+        .about("Reverts the last execution of the change set");
+}
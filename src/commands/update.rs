@@ -1,12 +1,19 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
 
-use clap::{ArgMatches, SubCommand, App};
+use clap::{ArgMatches, SubCommand, App, Arg};
 use itertools::{EitherOrBoth, Itertools};
 use yansi::Paint;
 
-use crate::{ProgramError, scan_tree};
+use crate::ProgramError;
+use crate::config::Config;
+use crate::escape::escape;
 use crate::changeset::{Action, ChangeSet, Workspace};
+use crate::fingerprint::Fingerprints;
+use crate::ignorelist::IgnoreList;
+use crate::order::Order;
+use crate::scan::{scan_tree, ScanFilter};
 
 pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
     let workspace = Workspace::open(workspace)
@@ -16,38 +23,269 @@ pub fn run(workspace: &Path, matches: &ArgMatches) -> Result<(), ProgramError> {
     let changeset = changeset.clean()
         .ok_or_else(|| ProgramError::NotClean)?;
 
+    let config = Config::load()?;
+    let ignores = IgnoreList::load(changeset.workspace())?
+        .with_global(&config)?;
+    let dry_run = matches.is_present("dry-run");
+
+    // A dry run uses the given filters without persisting them
+    let filter = if dry_run {
+        match ScanFilter::from_matches(matches)? {
+            Some(filter) => filter,
+            None => ScanFilter::load(changeset.workspace())?,
+        }
+    } else {
+        ScanFilter::resolve(changeset.workspace(), matches)?
+    };
+
     // Collect the current filesystem tree
-    let tree = scan_tree(changeset.path()).collect::<BTreeSet<_>>();
+    let tree = scan_tree(changeset.path(), &filter)?
+        .filter(|path| {
+            if ignores.is_ignored(path) {
+                verbose!("skipping {}: ignored", path.display());
+                return false;
+            }
+
+            return true;
+        })
+        .collect::<BTreeSet<_>>();
 
     // Get bi-directional difference to determine additions and deletions
     let (workspace, records) = changeset.split();
-    let records = Itertools::merge_join_by(tree.into_iter(), records,
-                                           |a, (b, _)| PathBuf::cmp(a, b))
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut records = Itertools::merge_join_by(tree.into_iter(), records,
+                                               |a, (b, _)| PathBuf::cmp(a, b))
         .filter_map(|difference| {
             match difference {
                 EitherOrBoth::Left(path) => {
-                    println!("{} {}", Paint::green("+").bold(), path.display());
-                    return Some((path.clone(), Action::Ignore(path.display().to_string())));
+                    added.push(path);
+                    return None;
                 }
                 EitherOrBoth::Right((path, action)) => {
-                    println!("{} {}", Paint::green("-").bold(), path.display());
+                    removed.push((path, action));
                     return None;
                 }
                 EitherOrBoth::Both(_, (path, action)) => {
+                    // Files showing up again get back the action they had before they went missing
+                    if let Some(action) = restore(&action) {
+                        info!("{} {}", Paint::green("✓").bold(), path.display());
+                        return Some((path, action));
+                    }
+
                     return Some((path, action));
                 }
             }
         })
-        .collect();
+        .collect::<BTreeMap<_, _>>();
+
+    let unchanged = records.len();
+    let keep_missing = matches.is_present("keep-missing");
+
+    // Carrying over the actions is confirmed for each file if running interactively
+    let carry_over = match matches.value_of("carry-over") {
+        Some(carry_over) => carry_over,
+        None if std::io::stdin().is_terminal() && !dry_run => "ask",
+        None => "always",
+    };
+
+    let mut removed_count = 0;
+    let mut renamed_count = 0;
+    let mut missing_count = 0;
+    let mut changed = false;
+
+    // Fingerprinting is enabled by request or by fingerprints persisted in the workspace
+    let fingerprints = match Fingerprints::load(&workspace)? {
+        Some(fingerprints) => Some(fingerprints),
+        None if matches.is_present("fingerprint") => Some(Fingerprints::new(config.checksum.algorithm.unwrap_or_default())),
+        None => None,
+    };
+
+    // Carry the actions of removed records over to added files with the same content
+    for (path, action) in removed {
+        let renamed = match (&fingerprints, &action) {
+            (_, Action::Ignore(_)) => None,
+            (_, _) if carry_over == "never" => None,
+            (Some(fingerprints), _) => find_renamed(fingerprints, workspace.path(), &path, &added)?,
+            (None, _) => None,
+        };
+
+        let renamed = match renamed {
+            Some(renamed) if carry_over == "ask" && !ask_carry_over(&path, &renamed)? => None,
+            renamed => renamed,
+        };
+
+        if let Some(renamed) = renamed {
+            info!("{} {} → {}", Paint::blue("↻").bold(), path.display(), renamed.display());
+            added.retain(|added| added != &renamed);
+            records.insert(renamed, action);
+            renamed_count += 1;
+            changed = true;
+            continue;
+        }
+
+        // Records with an action are kept as tombstones instead of being dropped silently
+        if keep_missing {
+            if restore(&action).is_some() {
+                records.insert(path, action);
+                missing_count += 1;
+                continue;
+            }
+
+            if !matches!(action, Action::Ignore(_)) {
+                info!("{} {}", Paint::yellow("?").bold(), path.display());
+                records.insert(path, tombstone(&action));
+                missing_count += 1;
+                changed = true;
+                continue;
+            }
+        }
+
+        info!("{} {}", Paint::green("-").bold(), path.display());
+        removed_count += 1;
+        changed = true;
+    }
+
+    let added_count = added.len();
+    for path in added {
+        info!("{} {}", Paint::green("+").bold(), path.display());
+        records.insert(path.clone(), Action::Ignore(escape(&path)));
+        changed = true;
+    }
 
-    let changeset = ChangeSet::create(workspace, records);
-    changeset.export()?;
+    let mut summary = vec![
+        format!("{} added", added_count),
+        format!("{} removed", removed_count),
+    ];
+    if renamed_count > 0 {
+        summary.push(format!("{} renamed", renamed_count));
+    }
+    if missing_count > 0 {
+        summary.push(format!("{} missing", missing_count));
+    }
+    summary.push(format!("{} unchanged", unchanged));
+    info!("{}", summary.join(", "));
+
+    if !dry_run {
+        if let Some(fingerprints) = fingerprints {
+            fingerprints.update(workspace.path(), records.keys())?
+                .save(&workspace)?;
+        }
+
+        let workspace = match matches.value_of("sort") {
+            Some(order) => workspace.with_order(order.parse::<Order>()?),
+            None => workspace,
+        };
+
+        let changeset = ChangeSet::create(workspace, records);
+        changeset.export()?;
+    }
+
+    if matches.is_present("exit-code") && changed {
+        return Err(ProgramError::Changed);
+    }
 
     return Ok(());
 }
 
+/// The comment marking records whose source went missing, followed by the previous action.
+const MISSING: &str = "missing:";
+
+/// Converts the action of a record whose source went missing into an ignored record keeping the
+/// previous action as comment.
+fn tombstone(action: &Action) -> Action {
+    return Action::Ignore(format!("{} {}", MISSING, action));
+}
+
+/// Returns the previous action of a tombstone.
+fn restore(action: &Action) -> Option<Action> {
+    return match action {
+        Action::Ignore(comment) => comment.strip_prefix(MISSING)
+            .map(|previous| Action::from(previous.trim_start())),
+        _ => None,
+    };
+}
+
+/// Asks the user whether the action of a removed file should be carried over to the added file
+/// with the same content.
+fn ask_carry_over(path: &Path, renamed: &Path) -> anyhow::Result<bool> {
+    loop {
+        print!("{} {} {} {} renamed, carry over the action? [y]es / [n]o ",
+               Paint::yellow("?").bold(), path.display(), Paint::blue("→"), renamed.display());
+        std::io::stdout().flush()?;
+
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer)? == 0 {
+            // Nothing more to read - treat the files as removed and added
+            println!();
+            return Ok(false);
+        }
+
+        match answer.trim() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => continue,
+        }
+    }
+}
+
+/// Finds the only added file with the same content as the removed file `path`.
+fn find_renamed(fingerprints: &Fingerprints, root: &Path, path: &Path, added: &[PathBuf]) -> anyhow::Result<Option<PathBuf>> {
+    let fingerprint = match fingerprints.get(path) {
+        Some(fingerprint) => fingerprint,
+        None => return Ok(None),
+    };
+
+    let mut candidates = Vec::new();
+    for added in added {
+        if std::fs::symlink_metadata(root.join(added))?.len() != fingerprint.size {
+            continue;
+        }
+
+        if let Some(other) = fingerprints.fingerprint(root, added)? {
+            if other.checksum == fingerprint.checksum {
+                candidates.push(added.clone());
+            }
+        }
+    }
+
+    if candidates.len() > 1 {
+        verbose!("not carrying over {}: {} files with identical content", path.display(), candidates.len());
+        return Ok(None);
+    }
+
+    return Ok(candidates.pop());
+}
+
 pub fn subcommand() -> App<'static, 'static> {
     return SubCommand::with_name("update")
         .alias("refresh")
-        .about("Refresh the input list and updates the change set");
+        .about("Refresh the input list and updates the change set")
+        .after_help("Filters given to update replace the filters persisted in the workspace.")
+        .arg(Arg::with_name("fingerprint")
+            .long("fingerprint")
+            .takes_value(false)
+            .help("Start remembering the checksums of all files to detect files renamed outside of mmv"))
+        .arg(Arg::with_name("carry-over")
+            .long("carry-over")
+            .value_name("WHEN")
+            .possible_values(&["ask", "always", "never"])
+            .help("Whether to carry the actions of renamed files over, defaults to ask when running in a terminal and always otherwise")
+            .takes_value(true))
+        .arg(Arg::with_name("dry-run")
+            .short("n")
+            .long("dry-run")
+            .takes_value(false)
+            .help("Print the additions and removals without updating the change set"))
+        .arg(Arg::with_name("exit-code")
+            .long("exit-code")
+            .takes_value(false)
+            .help("Exit with 1 if files were added or removed"))
+        .arg(Arg::with_name("keep-missing")
+            .long("keep-missing")
+            .takes_value(false)
+            .help("Keep records of vanished files having an action as ignored records marked as missing"))
+        .arg(Order::arg())
+        .args(&ScanFilter::args());
 }
\ No newline at end of file
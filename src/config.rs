@@ -0,0 +1,212 @@
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::Deserialize;
+use yansi::Paint;
+
+use crate::changeset::Workspace;
+use crate::hash::HashAlgorithm;
+
+/// The user configuration read from `$XDG_CONFIG_HOME/mmv/config.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Whether to color the output
+    pub color: ColorMode,
+
+    /// Glob patterns of files ignored in all workspaces
+    pub ignore: Vec<String>,
+
+    pub editor: EditorConfig,
+    pub checksum: ChecksumConfig,
+    pub execute: ExecuteConfig,
+    pub hooks: HooksConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Color the output if it is a terminal and `$NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct EditorConfig {
+    /// The name of a built-in editor preset
+    pub preset: Option<String>,
+
+    /// The full editor command line using `{sources}` and `{targets}` as placeholders
+    pub command: Option<String>,
+
+    /// The editor command line for workspaces using the combined format with `{changes}` as
+    /// placeholder
+    pub combined_command: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ChecksumConfig {
+    /// The algorithm used for checksums of file contents
+    pub algorithm: Option<HashAlgorithm>,
+}
+
+/// Defaults for `mmv execute`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ExecuteConfig {
+    /// Compare the checksums of copied files unless disabled with `--no-verify`
+    pub verify: bool,
+
+    /// Additional attributes replicated to copied files
+    pub preserve: Vec<PreserveAttribute>,
+
+    /// Continue with the remaining records after a failure
+    pub keep_going: bool,
+
+    /// Append a machine-readable log of each execution to the file
+    pub log_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreserveAttribute {
+    Xattr,
+    Acl,
+}
+
+/// Shell commands run during execution.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HooksConfig {
+    /// Run before touching any file with the plan as JSON on stdin, failing aborts the execution
+    pub pre_execute: Option<String>,
+
+    /// Run after the execution with the plan as JSON on stdin
+    pub post_execute: Option<String>,
+
+    /// Run after each completed record with the source and target in the environment
+    pub post_record: Option<String>,
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        let config = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        return Some(config.join("mmv").join("config.toml"));
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = match Self::path() {
+            Some(path) if path.is_file() => path,
+            _ => return Ok(Self::default()),
+        };
+
+        debug!("reading config {}", path.display());
+
+        let config = std::fs::read_to_string(&path)?;
+        let config = toml::from_str(&config)
+            .map_err(|err| anyhow::anyhow!("Invalid config {}: {}", path.display(), err))?;
+
+        return Ok(config);
+    }
+}
+
+/// The configuration of a single workspace read from `.mmv.toml` in its root.
+///
+/// The file travels with the directory and overrides the defaults for it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkspaceConfig {
+    /// The target directory if none is given, relative to the workspace
+    pub target: Option<PathBuf>,
+
+    /// Routing rules given as `GLOB=DIR`, applied after the ones given on the command line
+    pub routes: Vec<String>,
+
+    /// The scan filter if none is given
+    pub filter: Option<FilterConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FilterConfig {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub dir: Vec<String>,
+    pub empty_dirs: bool,
+    pub symlinks: Option<String>,
+    pub recreate_fifos: bool,
+    pub respect_gitignore: bool,
+    pub min_depth: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub hidden: Option<bool>,
+    pub ext: Vec<String>,
+    pub min_size: Option<String>,
+    pub max_size: Option<String>,
+    pub newer_than: Option<String>,
+    pub older_than: Option<String>,
+}
+
+impl WorkspaceConfig {
+    pub fn load(workspace: &Workspace) -> Result<Self> {
+        let path = workspace.config_path();
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+
+        debug!("reading workspace config {}", path.display());
+
+        let config = std::fs::read_to_string(&path)?;
+        let mut config: Self = toml::from_str(&config)
+            .map_err(|err| anyhow::anyhow!("Invalid config {}: {}", path.display(), err))?;
+
+        // Relative directories point into the workspace, regardless of the current directory
+        config.target = config.target.map(|target| workspace.path().join(target));
+        config.routes = config.routes.into_iter()
+            .map(|rule| match rule.split_once('=') {
+                Some((pattern, dir)) if !dir.is_empty() => format!("{}={}", pattern, workspace.path().join(dir).display()),
+                _ => rule,
+            })
+            .collect();
+
+        return Ok(config);
+    }
+}
+
+impl std::str::FromStr for ColorMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        return match s {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(anyhow::anyhow!("Invalid color mode: {}", s)),
+        };
+    }
+}
+
+impl ColorMode {
+    /// Enables or disables colored output accordingly.
+    pub fn apply(self) {
+        let enabled = match self {
+            ColorMode::Auto => std::env::var_os("NO_COLOR").filter(|value| !value.is_empty()).is_none() && std::io::stdout().is_terminal(),
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        };
+
+        if enabled {
+            Paint::enable();
+        } else {
+            Paint::disable();
+        }
+    }
+}
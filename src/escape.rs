@@ -0,0 +1,80 @@
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+/// Escapes a path for the change set files.
+///
+/// Paths are not required to be valid UTF-8. Bytes which are not valid UTF-8 are written as `\xNN`
+/// and backslashes are doubled, so every path can be restored using `unescape`. Newlines, tabs and
+/// other control characters are escaped to keep every path on a single line. A leading space is
+/// escaped as it would mark the line as ignored.
+pub fn escape(path: &Path) -> String {
+    let mut result = String::new();
+
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => result.push_str("\\\\"),
+                '\n' => result.push_str("\\n"),
+                '\t' => result.push_str("\\t"),
+                '\r' => result.push_str("\\r"),
+                ' ' if result.is_empty() => result.push_str("\\x20"),
+                c if c.is_ascii_control() => result.push_str(&format!("\\x{:02x}", c as u8)),
+                c => result.push(c),
+            }
+        }
+
+        for byte in chunk.invalid() {
+            result.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+
+    return result;
+}
+
+/// Restores a path escaped by `escape`.
+///
+/// Backslashes not starting a valid escape sequence are kept as they are.
+pub fn unescape(s: &str) -> PathBuf {
+    let mut result = Vec::with_capacity(s.len());
+
+    let mut bytes = s.as_bytes();
+    while let Some((&byte, rest)) = bytes.split_first() {
+        bytes = rest;
+
+        if byte != b'\\' {
+            result.push(byte);
+            continue;
+        }
+
+        match bytes {
+            [b'\\', rest @ ..] => {
+                result.push(b'\\');
+                bytes = rest;
+            }
+            [b'n', rest @ ..] => {
+                result.push(b'\n');
+                bytes = rest;
+            }
+            [b't', rest @ ..] => {
+                result.push(b'\t');
+                bytes = rest;
+            }
+            [b'r', rest @ ..] => {
+                result.push(b'\r');
+                bytes = rest;
+            }
+            [b'x', hi, lo, rest @ ..] if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() => {
+                let hex = [*hi, *lo];
+                let hex = std::str::from_utf8(&hex).expect("Invalid hex");
+                result.push(u8::from_str_radix(hex, 16).expect("Invalid hex"));
+                bytes = rest;
+            }
+            _ => {
+                result.push(b'\\');
+            }
+        }
+    }
+
+    return PathBuf::from(OsString::from_vec(result));
+}
@@ -0,0 +1,55 @@
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+/// Expands environment variables and the home directory in a target path.
+///
+/// Variables are written as `$VAR` or `${VAR}` and must be defined. A `$` not followed by a
+/// variable name is kept as it is. A leading `~` component is replaced by `$HOME`.
+pub fn expand(path: &Path) -> Result<PathBuf> {
+    let bytes = path.as_os_str().as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+
+    let mut rest = bytes;
+    if rest == b"~" || rest.starts_with(b"~/") {
+        result.extend_from_slice(var("HOME")?.as_bytes());
+        rest = &rest[1..];
+    }
+
+    while let Some(pos) = rest.iter().position(|&b| b == b'$') {
+        result.extend_from_slice(&rest[..pos]);
+        rest = &rest[pos + 1..];
+
+        let (name, len) = if rest.starts_with(b"{") {
+            let end = rest.iter().position(|&b| b == b'}')
+                .ok_or_else(|| anyhow!("Unterminated variable in {}", path.display()))?;
+            (&rest[1..end], end + 1)
+        } else {
+            let end = rest.iter().position(|&b| !(b.is_ascii_alphanumeric() || b == b'_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], end)
+        };
+
+        if name.is_empty() || name[0].is_ascii_digit() {
+            result.push(b'$');
+            continue;
+        }
+
+        let name = std::str::from_utf8(name)
+            .map_err(|_| anyhow!("Invalid variable name in {}", path.display()))?;
+
+        result.extend_from_slice(var(name)?.as_bytes());
+        rest = &rest[len..];
+    }
+
+    result.extend_from_slice(rest);
+
+    return Ok(PathBuf::from(OsString::from_vec(result)));
+}
+
+fn var(name: &str) -> Result<OsString> {
+    return std::env::var_os(name)
+        .ok_or_else(|| anyhow!("Environment variable not set: {}", name));
+}
@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{anyhow, Result};
+
+use crate::changeset::Workspace;
+use crate::escape::{escape, unescape};
+use crate::hash::HashAlgorithm;
+
+/// The size and checksum of a file along with its modification time, which allows to skip
+/// calculating the checksum again for unmodified files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub size: u64,
+    pub mtime: u128,
+    pub checksum: String,
+}
+
+/// The fingerprints of all files in the workspace used to recognize files renamed outside of mmv.
+///
+/// The fingerprints are stored along with the change set, one file per line formatted as
+/// `SIZE\tMTIME\tCHECKSUM\tPATH` after a header line naming the algorithm.
+#[derive(Debug, Clone)]
+pub struct Fingerprints {
+    algorithm: HashAlgorithm,
+    entries: BTreeMap<PathBuf, Fingerprint>,
+}
+
+impl Fingerprints {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        return Self {
+            algorithm,
+            entries: BTreeMap::new(),
+        };
+    }
+
+    /// Loads the fingerprints or returns `None` if fingerprinting is not enabled for the workspace.
+    pub fn load(workspace: &Workspace) -> Result<Option<Self>> {
+        let path = workspace.fingerprints_path();
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let mut lines = BufReader::new(File::open(&path)?).lines();
+
+        let algorithm = match lines.next().transpose()? {
+            Some(header) => header.strip_prefix("algorithm ")
+                .ok_or_else(|| anyhow!("Invalid fingerprints header in {}", path.display()))?
+                .parse()?,
+            None => HashAlgorithm::default(),
+        };
+
+        let mut entries = BTreeMap::new();
+        for line in lines {
+            let line = line?;
+            let invalid = || anyhow!("Invalid fingerprint in {}: {}", path.display(), line);
+
+            let mut fields = line.splitn(4, '\t');
+            let (size, mtime, checksum, file) = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(size), Some(mtime), Some(checksum), Some(file)) => (size, mtime, checksum, file),
+                _ => return Err(invalid()),
+            };
+
+            entries.insert(unescape(file), Fingerprint {
+                size: size.parse().map_err(|_| invalid())?,
+                mtime: mtime.parse().map_err(|_| invalid())?,
+                checksum: checksum.to_string(),
+            });
+        }
+
+        return Ok(Some(Self {
+            algorithm,
+            entries,
+        }));
+    }
+
+    pub fn save(&self, workspace: &Workspace) -> Result<()> {
+        let mut file = File::create(workspace.fingerprints_path())?;
+
+        writeln!(file, "algorithm {}", self.algorithm)?;
+        for (path, fingerprint) in self.entries.iter() {
+            writeln!(file, "{}\t{}\t{}\t{}", fingerprint.size, fingerprint.mtime, fingerprint.checksum, escape(path))?;
+        }
+
+        return Ok(());
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&Fingerprint> {
+        return self.entries.get(path);
+    }
+
+    /// Takes the fingerprints of the given files in `root`, reusing the previous fingerprints of
+    /// files whose size and modification time did not change.
+    ///
+    /// Directories and other special records are not fingerprinted.
+    pub fn update<'a>(self, root: &Path, paths: impl IntoIterator<Item=&'a PathBuf>) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+
+        for path in paths {
+            let fingerprint = match self.fingerprint(root, path)? {
+                Some(fingerprint) => fingerprint,
+                None => continue,
+            };
+
+            entries.insert(path.clone(), fingerprint);
+        }
+
+        return Ok(Self {
+            algorithm: self.algorithm,
+            entries,
+        });
+    }
+
+    /// Takes the fingerprint of a single file, reusing the previous one if the file is unchanged.
+    pub fn fingerprint(&self, root: &Path, path: &Path) -> Result<Option<Fingerprint>> {
+        let full = root.join(path);
+
+        let metadata = std::fs::symlink_metadata(&full)?;
+        if !metadata.is_file() {
+            return Ok(None);
+        }
+
+        let size = metadata.len();
+        let mtime = metadata.modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|mtime| mtime.as_nanos())
+            .unwrap_or_default();
+
+        if let Some(previous) = self.entries.get(path) {
+            if previous.size == size && previous.mtime == mtime {
+                return Ok(Some(previous.clone()));
+            }
+        }
+
+        debug!("fingerprinting {}", path.display());
+
+        let checksum = self.algorithm.checksum(&full)?.iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        return Ok(Some(Fingerprint {
+            size,
+            mtime,
+            checksum,
+        }));
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::Write;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Result};
+
+/// A git repository containing the workspace.
+#[derive(Debug, Clone)]
+pub struct Repository {
+    root: PathBuf,
+}
+
+impl Repository {
+    /// Finds the repository containing the given directory.
+    pub fn discover(path: &Path) -> Result<Option<Self>> {
+        let output = Command::new("git")
+            .arg("-C").arg(path)
+            .args(["rev-parse", "--show-toplevel"])
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|err| anyhow!("Failed to run git: {}", err))?;
+
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        let mut root = output.stdout;
+        while root.last() == Some(&b'\n') {
+            root.pop();
+        }
+
+        return Ok(Some(Self {
+            root: PathBuf::from(OsString::from_vec(root)),
+        }));
+    }
+
+    /// Records moved and deleted files in the index, like `git mv` and `git rm --cached` would.
+    ///
+    /// The index entries of moved files are carried over to their targets, so staged and unstaged
+    /// changes are kept as they are. Files not tracked by git are left alone, files moved out of
+    /// the repository are removed from the index.
+    ///
+    /// Returns the paths changed in the index, relative to the repository root.
+    pub fn update_index(&self, moves: &[(PathBuf, PathBuf)], deletes: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let entries = self.entries()?;
+
+        // The new index entries given as mode, object ID and path. Removed entries have a zero mode
+        // and object ID
+        let mut updates = Vec::new();
+
+        // Moved or deleted directories carry all files below them
+        let tracked = |source: &Path| entries.iter()
+            .filter(|(path, _)| path.starts_with(source))
+            .map(|(path, entry)| (path, path.strip_prefix(source).expect("Path not below source"), entry))
+            .collect::<Vec<_>>();
+
+        for (source, target) in moves.iter() {
+            let source = match self.relative(source) {
+                Some(source) => source,
+                None => continue,
+            };
+
+            let target = self.relative(target);
+
+            for (path, rest, (mode, object)) in tracked(&source) {
+                updates.push(("0".to_string(), "0".repeat(object.len()), path.clone()));
+
+                if let Some(target) = target.as_ref() {
+                    let target = if rest.as_os_str().is_empty() { target.clone() } else { target.join(rest) };
+                    updates.push((mode.clone(), object.clone(), target));
+                }
+            }
+        }
+
+        for source in deletes.iter() {
+            let source = match self.relative(source) {
+                Some(source) => source,
+                None => continue,
+            };
+
+            for (path, _, (_, object)) in tracked(&source) {
+                updates.push(("0".to_string(), "0".repeat(object.len()), path.clone()));
+            }
+        }
+
+        if updates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        debug!("updating {} git index entries in {}", updates.len(), self.root.display());
+
+        let mut input = Vec::new();
+        for (mode, object, path) in updates.iter() {
+            input.extend_from_slice(format!("{} {}\t", mode, object).as_bytes());
+            input.extend_from_slice(path.as_os_str().as_bytes());
+            input.push(0);
+        }
+
+        let mut child = Command::new("git")
+            .arg("-C").arg(&self.root)
+            .args(["update-index", "-z", "--index-info"])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| anyhow!("Failed to run git: {}", err))?;
+
+        child.stdin.take().expect("No stdin").write_all(&input)?;
+
+        if !child.wait()?.success() {
+            return Err(anyhow!("Failed to update the git index"));
+        }
+
+        return Ok(updates.into_iter()
+            .map(|(_, _, path)| path)
+            .collect());
+    }
+
+    /// Commits the index entries of the given paths, relative to the repository root, leaving
+    /// other staged changes uncommitted.
+    ///
+    /// The commit is created from a temporary index holding the current commit with the entries of
+    /// the given paths taken from the index, so neither other staged changes nor the content of the
+    /// working tree end up in the commit.
+    pub fn commit(&self, message: &str, paths: &[PathBuf]) -> Result<()> {
+        debug!("committing {} paths in {}", paths.len(), self.root.display());
+
+        let entries = self.entries()?;
+
+        let output = Command::new("git")
+            .arg("-C").arg(&self.root)
+            .args(["rev-parse", "--quiet", "--verify", "HEAD"])
+            .output()
+            .map_err(|err| anyhow!("Failed to run git: {}", err))?;
+
+        // There is no current commit in a new repository
+        let head = Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .filter(|head| output.status.success() && !head.is_empty());
+
+        let temporary = tempfile::tempdir()?;
+        let index = temporary.path().join("index");
+
+        if let Some(head) = head.as_ref() {
+            let status = Command::new("git")
+                .arg("-C").arg(&self.root)
+                .env("GIT_INDEX_FILE", &index)
+                .args(["read-tree", head])
+                .status()
+                .map_err(|err| anyhow!("Failed to run git: {}", err))?;
+
+            if !status.success() {
+                return Err(anyhow!("Failed to read the current commit"));
+            }
+        }
+
+        // Paths missing in the index are removed from the commit, which is only required if there
+        // is a current commit to remove them from
+        let mut input = Vec::new();
+        for path in paths.iter() {
+            match (entries.get(path), head.as_ref()) {
+                (Some((mode, object)), _) => input.extend_from_slice(format!("{} {}\t", mode, object).as_bytes()),
+                (None, Some(head)) => input.extend_from_slice(format!("0 {}\t", "0".repeat(head.len())).as_bytes()),
+                (None, None) => continue,
+            }
+
+            input.extend_from_slice(path.as_os_str().as_bytes());
+            input.push(0);
+        }
+
+        let mut child = Command::new("git")
+            .arg("-C").arg(&self.root)
+            .env("GIT_INDEX_FILE", &index)
+            .args(["update-index", "-z", "--index-info"])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| anyhow!("Failed to run git: {}", err))?;
+
+        child.stdin.take().expect("No stdin").write_all(&input)?;
+
+        if !child.wait()?.success() {
+            return Err(anyhow!("Failed to prepare the commit"));
+        }
+
+        let status = Command::new("git")
+            .arg("-C").arg(&self.root)
+            .env("GIT_INDEX_FILE", &index)
+            .args(["commit", "--quiet"])
+            .arg("--message").arg(message)
+            .status()
+            .map_err(|err| anyhow!("Failed to run git: {}", err))?;
+
+        if !status.success() {
+            return Err(anyhow!("Failed to commit"));
+        }
+
+        return Ok(());
+    }
+
+    /// Returns the mode and object ID of all files in the index.
+    fn entries(&self) -> Result<BTreeMap<PathBuf, (String, String)>> {
+        let output = Command::new("git")
+            .arg("-C").arg(&self.root)
+            .args(["ls-files", "-s", "-z"])
+            .output()
+            .map_err(|err| anyhow!("Failed to run git: {}", err))?;
+
+        if !output.status.success() {
+            return Err(anyhow!("Failed to read the git index"));
+        }
+
+        // Each entry is formatted as `MODE OBJECT STAGE\tPATH`. Conflicting entries with a
+        // non-zero stage are left alone
+        let mut entries = BTreeMap::new();
+        for entry in output.stdout.split(|b| *b == 0).filter(|entry| !entry.is_empty()) {
+            let tab = entry.iter().position(|b| *b == b'\t')
+                .ok_or_else(|| anyhow!("Invalid git index entry"))?;
+
+            let info = String::from_utf8_lossy(&entry[..tab]);
+            let path = PathBuf::from(OsString::from_vec(entry[tab + 1..].to_vec()));
+
+            if let [mode, object, "0"] = info.split(' ').collect::<Vec<_>>()[..] {
+                entries.insert(path, (mode.to_string(), object.to_string()));
+            }
+        }
+
+        return Ok(entries);
+    }
+
+    /// Returns the path relative to the repository root or `None` if it is outside.
+    ///
+    /// The root reported by git has all symlinks resolved, so the parent of the path is resolved
+    /// as well if it exists.
+    fn relative(&self, path: &Path) -> Option<PathBuf> {
+        let path = std::path::absolute(path).ok()?;
+        let path = match (path.parent().map(std::fs::canonicalize), path.file_name()) {
+            (Some(Ok(parent)), Some(name)) => parent.join(name),
+            _ => path,
+        };
+
+        return path.strip_prefix(&self.root).ok()
+            .map(Path::to_path_buf);
+    }
+}
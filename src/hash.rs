@@ -0,0 +1,82 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// The algorithms available for checksums of file contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Blake3,
+    Sha256,
+    Xxh3,
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            HashAlgorithm::Blake3 => write!(f, "blake3"),
+            HashAlgorithm::Sha256 => write!(f, "sha256"),
+            HashAlgorithm::Xxh3 => write!(f, "xxh3"),
+        };
+    }
+}
+
+impl std::str::FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "xxh3" => Ok(HashAlgorithm::Xxh3),
+            _ => Err(anyhow!("Invalid hash algorithm: {}", s)),
+        };
+    }
+}
+
+impl HashAlgorithm {
+    pub const NAMES: &'static [&'static str] = &["blake3", "sha256", "xxh3"];
+
+    /// Calculates the checksum of the content of a file.
+    pub fn checksum(self, path: &Path) -> Result<Vec<u8>> {
+        return match self {
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                read_chunks(path, |chunk| { hasher.update(chunk); })?;
+                Ok(hasher.finalize().as_bytes().to_vec())
+            }
+
+            HashAlgorithm::Sha256 => {
+                let mut hasher = Sha256::new();
+                read_chunks(path, |chunk| hasher.update(chunk))?;
+                Ok(hasher.finalize().to_vec())
+            }
+
+            HashAlgorithm::Xxh3 => {
+                let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+                read_chunks(path, |chunk| hasher.update(chunk))?;
+                Ok(hasher.digest128().to_be_bytes().to_vec())
+            }
+        };
+    }
+}
+
+/// Feeds the content of a file to `f` in chunks.
+fn read_chunks(path: &Path, mut f: impl FnMut(&[u8])) -> Result<()> {
+    let mut file = File::open(path)?;
+
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let len = file.read(&mut buffer)?;
+        if len == 0 {
+            return Ok(());
+        }
+
+        f(&buffer[..len]);
+    }
+}
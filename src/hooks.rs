@@ -0,0 +1,35 @@
+use std::ffi::OsStr;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Result};
+
+/// Runs a hook command using the shell.
+///
+/// The command gets the given environment variables and `input` on stdin. Hooks not reading their
+/// input are fine.
+pub fn run<K, V>(name: &str, command: &str, env: impl IntoIterator<Item=(K, V)>, input: &[u8]) -> Result<()>
+    where K: AsRef<OsStr>,
+          V: AsRef<OsStr> {
+    debug!("running {} hook: {}", name, command);
+
+    let mut child = Command::new("sh")
+        .arg("-c").arg(command)
+        .envs(env)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| anyhow!("Failed to run {} hook: {}", name, err))?;
+
+    match child.stdin.take().expect("No stdin").write_all(input) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("The {} hook failed: {}", name, status));
+    }
+
+    return Ok(());
+}
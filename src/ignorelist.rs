@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::changeset::Workspace;
+use crate::config::Config;
+
+/// The list of glob patterns for files which are permanently excluded from the change set.
+///
+/// Patterns are matched against the path relative to the workspace and against the file name. This
+/// allows to ignore files by name (`Thumbs.db`) regardless of their location.
+#[derive(Debug, Clone)]
+pub struct IgnoreList {
+    patterns: Vec<String>,
+
+    set: GlobSet,
+}
+
+impl IgnoreList {
+    pub fn create(patterns: Vec<String>) -> Result<Self> {
+        let mut set = GlobSetBuilder::new();
+        for pattern in patterns.iter() {
+            set.add(Glob::new(pattern)?);
+        }
+
+        return Ok(Self {
+            patterns,
+            set: set.build()?,
+        });
+    }
+
+    pub fn load(workspace: &Workspace) -> Result<Self> {
+        let path = workspace.ignore_path();
+        if !path.is_file() {
+            return Self::create(Vec::new());
+        }
+
+        let patterns = BufReader::new(File::open(path)?).lines()
+            .filter(|line| match line {
+                Ok(line) => !line.trim().is_empty() && !line.starts_with('#'),
+                Err(_) => true,
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        return Self::create(patterns);
+    }
+
+    pub fn save(&self, workspace: &Workspace) -> Result<()> {
+        let mut file = File::create(workspace.ignore_path())?;
+
+        for pattern in self.patterns.iter() {
+            writeln!(file, "{}", pattern)?;
+        }
+
+        return Ok(());
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        return &self.patterns;
+    }
+
+    pub fn add(self, pattern: impl Into<String>) -> Result<Self> {
+        let mut patterns = self.patterns;
+        patterns.push(pattern.into());
+
+        return Self::create(patterns);
+    }
+
+    /// Adds the patterns ignored in all workspaces, which are not saved along with the list.
+    pub fn with_global(self, config: &Config) -> Result<Self> {
+        let mut set = GlobSetBuilder::new();
+        for pattern in self.patterns.iter().chain(config.ignore.iter()) {
+            set.add(Glob::new(pattern)?);
+        }
+
+        return Ok(Self {
+            patterns: self.patterns,
+            set: set.build()?,
+        });
+    }
+
+    pub fn remove(self, pattern: &str) -> Result<Self> {
+        let mut patterns = self.patterns;
+        patterns.retain(|p| p != pattern);
+
+        return Self::create(patterns);
+    }
+
+    pub fn is_ignored(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+
+        if self.set.is_match(path) {
+            return true;
+        }
+
+        return path.file_name()
+            .map(|name| self.set.is_match(name))
+            .unwrap_or(false);
+    }
+}
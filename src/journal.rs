@@ -0,0 +1,194 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::changeset::Workspace;
+use crate::escape::{escape, unescape};
+
+/// A completed action recorded in the journal.
+///
+/// All paths are absolute as the target directory is only known during execution.
+#[derive(Debug, Clone)]
+pub enum JournalEntry {
+    Move { source: PathBuf, target: PathBuf },
+    Link { source: PathBuf, target: PathBuf },
+    Copy { source: PathBuf, target: PathBuf },
+    Symlink { path: PathBuf, target: PathBuf },
+    Delete { source: PathBuf },
+}
+
+impl JournalEntry {
+    pub fn moved(source: impl AsRef<Path>, target: impl AsRef<Path>) -> Result<Self> {
+        return Ok(JournalEntry::Move {
+            source: std::path::absolute(source)?,
+            target: std::path::absolute(target)?,
+        });
+    }
+
+    pub fn linked(source: impl AsRef<Path>, target: impl AsRef<Path>) -> Result<Self> {
+        return Ok(JournalEntry::Link {
+            source: std::path::absolute(source)?,
+            target: std::path::absolute(target)?,
+        });
+    }
+
+    pub fn copied(source: impl AsRef<Path>, target: impl AsRef<Path>) -> Result<Self> {
+        return Ok(JournalEntry::Copy {
+            source: std::path::absolute(source)?,
+            target: std::path::absolute(target)?,
+        });
+    }
+
+    pub fn symlinked(path: impl AsRef<Path>, target: impl AsRef<Path>) -> Result<Self> {
+        return Ok(JournalEntry::Symlink {
+            path: std::path::absolute(path)?,
+            target: std::path::absolute(target)?,
+        });
+    }
+
+    pub fn deleted(source: impl AsRef<Path>) -> Result<Self> {
+        return Ok(JournalEntry::Delete {
+            source: std::path::absolute(source)?,
+        });
+    }
+}
+
+impl std::fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            JournalEntry::Move { source, target } => write!(f, "M\t{}\t{}", escape(source), escape(target)),
+            JournalEntry::Link { source, target } => write!(f, "L\t{}\t{}", escape(source), escape(target)),
+            JournalEntry::Copy { source, target } => write!(f, "C\t{}\t{}", escape(source), escape(target)),
+            JournalEntry::Symlink { path, target } => write!(f, "S\t{}\t{}", escape(path), escape(target)),
+            JournalEntry::Delete { source } => write!(f, "D\t{}", escape(source)),
+        };
+    }
+}
+
+impl std::str::FromStr for JournalEntry {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut fields = s.split('\t');
+        return match (fields.next(), fields.next(), fields.next()) {
+            (Some("M"), Some(source), Some(target)) => Ok(JournalEntry::Move {
+                source: unescape(source),
+                target: unescape(target),
+            }),
+            (Some("L"), Some(source), Some(target)) => Ok(JournalEntry::Link {
+                source: unescape(source),
+                target: unescape(target),
+            }),
+            (Some("C"), Some(source), Some(target)) => Ok(JournalEntry::Copy {
+                source: unescape(source),
+                target: unescape(target),
+            }),
+            (Some("S"), Some(path), Some(target)) => Ok(JournalEntry::Symlink {
+                path: unescape(path),
+                target: unescape(target),
+            }),
+            (Some("D"), Some(source), None) => Ok(JournalEntry::Delete {
+                source: unescape(source),
+            }),
+            _ => Err(anyhow!("Invalid journal entry: {}", s)),
+        };
+    }
+}
+
+/// The journal of actions completed during the last execution.
+///
+/// The journal starts with the target directory of the execution. Every entry is flushed to disk
+/// immediately, so the journal reflects the completed actions even if the execution was aborted.
+pub struct Journal {
+    file: BufWriter<File>,
+}
+
+impl Journal {
+    /// The number of journals of earlier executions kept next to the current one.
+    pub const PREVIOUS: usize = 9;
+
+    /// Starts a new journal. The journal of the last execution is kept as `.mmv.journal.1`,
+    /// shifting older ones up to the oldest kept journal.
+    pub fn create(workspace: &Workspace, target: &Path) -> Result<Self> {
+        if workspace.journal_path().is_file() {
+            for n in (1..Self::PREVIOUS).rev() {
+                let previous = workspace.previous_journal_path(n);
+                if previous.is_file() {
+                    std::fs::rename(&previous, workspace.previous_journal_path(n + 1))?;
+                }
+            }
+
+            std::fs::rename(workspace.journal_path(), workspace.previous_journal_path(1))?;
+        }
+
+        let file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(workspace.journal_path())?;
+
+        let mut journal = Self {
+            file: BufWriter::new(file),
+        };
+
+        writeln!(journal.file, "T\t{}", escape(&std::path::absolute(target)?))?;
+        journal.file.flush()?;
+
+        return Ok(journal);
+    }
+
+    /// Opens the existing journal to continue an aborted execution.
+    pub fn append(workspace: &Workspace) -> Result<Self> {
+        let file = File::options()
+            .append(true)
+            .open(workspace.journal_path())?;
+
+        return Ok(Self {
+            file: BufWriter::new(file),
+        });
+    }
+
+    pub fn record(&mut self, entry: JournalEntry) -> Result<()> {
+        debug!("journal {}", entry);
+
+        writeln!(self.file, "{}", entry)?;
+        self.file.flush()?;
+
+        return Ok(());
+    }
+
+    /// Returns the length of the journal written so far.
+    pub fn len(&mut self) -> Result<u64> {
+        self.file.flush()?;
+
+        return Ok(self.file.get_ref().metadata()?.len());
+    }
+
+    /// Drops all entries recorded after the journal had the given length.
+    pub fn truncate(&mut self, len: u64) -> Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().set_len(len)?;
+        self.file.seek(SeekFrom::Start(len))?;
+
+        return Ok(());
+    }
+
+    pub fn load(workspace: &Workspace) -> Result<Vec<JournalEntry>> {
+        return BufReader::new(File::open(workspace.journal_path())?).lines()
+            .filter(|line| !matches!(line, Ok(line) if line.starts_with("T\t")))
+            .map(|line| line?.parse())
+            .collect();
+    }
+
+    /// Returns the target directory of the execution recorded in the journal.
+    pub fn target(workspace: &Workspace) -> Result<Option<PathBuf>> {
+        for line in BufReader::new(File::open(workspace.journal_path())?).lines() {
+            if let Some(target) = line?.strip_prefix("T\t") {
+                return Ok(Some(unescape(target)));
+            }
+        }
+
+        return Ok(None);
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use serde_json::{json, Value};
+
+use crate::escape::escape;
+
+/// A machine-readable log of executions.
+///
+/// Each line is a JSON object with the time and the kind of the event. An execution starts with a
+/// `start` event followed by a `record` event for each file operation and ends with a `finish`
+/// event. Executions are appended to existing logs.
+pub struct Log {
+    file: File,
+}
+
+/// A file operation of a record.
+pub struct Operation<'a> {
+    pub record: &'a Path,
+
+    /// Either `move` or `delete`
+    pub action: &'static str,
+
+    pub source: &'a Path,
+    pub target: Option<&'a Path>,
+
+    /// Size of the moved file
+    pub bytes: u64,
+
+    pub duration: Duration,
+}
+
+impl Log {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| anyhow!("Failed to open log {}: {}", path.display(), err))?;
+
+        return Ok(Self { file });
+    }
+
+    pub fn start(&mut self, workspace: &Path, target: &Path, records: usize) -> Result<()> {
+        return self.write("start", json!({
+            "workspace": escape(workspace),
+            "target": escape(target),
+            "records": records,
+        }));
+    }
+
+    /// Logs a single file operation, failed operations carry the error.
+    pub fn record(&mut self, operation: Operation, error: Option<&anyhow::Error>) -> Result<()> {
+        return self.write("record", json!({
+            "record": escape(operation.record),
+            "action": operation.action,
+            "source": escape(operation.source),
+            "target": operation.target.map(escape),
+            "result": if error.is_none() { "success" } else { "failure" },
+            "error": error.map(ToString::to_string),
+            "bytes": operation.bytes,
+            "duration": operation.duration.as_secs_f64(),
+        }));
+    }
+
+    pub fn finish(&mut self, status: &str, duration: Duration) -> Result<()> {
+        return self.write("finish", json!({
+            "status": status,
+            "duration": duration.as_secs_f64(),
+        }));
+    }
+
+    fn write(&mut self, event: &str, fields: Value) -> Result<()> {
+        let mut entry = json!({
+            "time": Local::now().to_rfc3339(),
+            "event": event,
+        });
+
+        if let (Some(entry), Value::Object(fields)) = (entry.as_object_mut(), fields) {
+            entry.extend(fields);
+        }
+
+        writeln!(self.file, "{}", entry)?;
+        self.file.flush()?;
+
+        return Ok(());
+    }
+}
@@ -1,11 +1,34 @@
-use std::path::{Path, PathBuf};
+#![allow(clippy::needless_return)]
+
+use std::path::PathBuf;
 
 use clap::{App, AppSettings, Arg};
-use walkdir::WalkDir;
 use yansi::Paint;
 
+#[macro_use]
+mod verbosity;
+
 mod changeset;
 mod commands;
+mod config;
+mod escape;
+mod expand;
+mod fingerprint;
+mod git;
+mod hash;
+mod hooks;
+mod ignorelist;
+mod journal;
+mod log;
+mod metadata;
+mod order;
+mod plugins;
+mod progress;
+mod routes;
+mod scan;
+mod template;
+mod throttle;
+mod units;
 
 #[derive(thiserror::Error, Debug)]
 pub enum ProgramError {
@@ -15,54 +38,28 @@ pub enum ProgramError {
     #[error("Not clean")]
     NotClean,
 
-    #[error(transparent)]
-    InternalError(#[from] anyhow::Error),
-}
+    #[error("Invalid change set")]
+    Invalid,
 
-fn scan_tree<'a>(workdir: impl AsRef<Path> + 'a) -> impl Iterator<Item=PathBuf> + 'a {
-    return WalkDir::new(workdir.as_ref())
-        .min_depth(1)
-        .sort_by(|a, b| Ord::cmp(a.file_name(), b.file_name()))
-        .into_iter()
-        .filter_map(|entry| {
-            match entry {
-                Ok(entry) => {
-                    return Some(entry);
-                }
-                Err(err) => {
-                    eprintln!("{}", err);
-                    return None;
-                }
-            }
-        })
-        .filter_map(move |entry| {
-            // Only list files
-            if !entry.file_type().is_file() {
-                return None;
-            }
+    #[error("Interrupted")]
+    Interrupted,
 
-            // Ignore mmv status files
-            if entry.file_name().to_str()
-                .map(|s| s.starts_with(".mmv"))
-                .unwrap_or(false) {
-                return None;
-            }
+    /// The workspace differs from the change set, reported on request only
+    #[error("Changed")]
+    Changed,
 
-            // The path is absolute. The common prefix is removed to make the path relative to the
-            // working directory
-            let path = entry.path().strip_prefix(workdir.as_ref())
-                .expect("Path not relative");
-
-            return Some(path.to_path_buf());
-        });
+    #[error(transparent)]
+    InternalError(#[from] anyhow::Error),
 }
 
-fn main() -> Result<(), anyhow::Error> {
-    let matches = App::new("mmv")
+/// The command line interface including all subcommands.
+pub fn app() -> App<'static, 'static> {
+    return App::new("mmv")
         .about("Mass Move files with interactive renaming")
         .version(env!("CARGO_PKG_VERSION"))
         .author("Dustin Frisch <fooker@lab.sh>")
         .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::AllowExternalSubcommands)
         .arg(Arg::with_name("source")
             .short("s")
             .long("source")
@@ -70,27 +67,119 @@ fn main() -> Result<(), anyhow::Error> {
             .help("The source directory to work on")
             .takes_value(true)
             .required(false))
-        // .arg(Arg::with_name("verbose")
-        //     .short("v")
-        //     .long("verbose")
-        //     .help("Enables detailed output"))
-        .subcommand(commands::init::subcommand())
-        .subcommand(commands::update::subcommand())
-        .subcommand(commands::status::subcommand())
-        .subcommand(commands::edit::subcommand())
-        .subcommand(commands::execute::subcommand())
-        .get_matches();
+        .arg(Arg::with_name("changeset")
+            .short("c")
+            .long("changeset")
+            .value_name("NAME")
+            .help("Work on the named change set instead of the current one")
+            .takes_value(true))
+        .arg(Arg::with_name("color")
+            .long("color")
+            .value_name("WHEN")
+            .help("Whether to color the output, defaults to the configured mode or auto")
+            .takes_value(true)
+            .require_equals(true)
+            .possible_values(&["auto", "always", "never"]))
+        .arg(Arg::with_name("verbose")
+            .short("v")
+            .long("verbose")
+            .multiple(true)
+            .help("Print details about each record, twice to trace the individual operations"))
+        .arg(Arg::with_name("quiet")
+            .short("q")
+            .long("quiet")
+            .conflicts_with("verbose")
+            .help("Print nothing but errors and the requested output"))
+        .subcommands(subcommands());
+}
+
+/// All subcommands in the order they are listed in the help.
+pub fn subcommands() -> Vec<App<'static, 'static>> {
+    return vec![
+        commands::init::subcommand(),
+        commands::update::subcommand(),
+        commands::status::subcommand(),
+        commands::check::subcommand(),
+        commands::list::subcommand(),
+        commands::grep::subcommand(),
+        commands::edit::subcommand(),
+        commands::execute::subcommand(),
+        commands::resume::subcommand(),
+        commands::ignore::subcommand(),
+        commands::undo::subcommand(),
+        commands::sub::subcommand(),
+        commands::template::subcommand(),
+        commands::renumber::subcommand(),
+        commands::transform::subcommand(),
+        commands::dedupe::subcommand(),
+        commands::diff_dirs::subcommand(),
+        commands::set::subcommand(),
+        commands::rm::subcommand(),
+        commands::keep::subcommand(),
+        commands::import::subcommand(),
+        commands::reverse::subcommand(),
+        commands::abort::subcommand(),
+        commands::stash::subcommand(),
+        commands::completions::subcommand(),
+        commands::man::subcommand(),
+    ];
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let matches = app().get_matches();
+
+    if let Some(name) = matches.value_of("changeset") {
+        changeset::select(name)?;
+    }
 
     let workspace = matches.value_of("source")
         .map(PathBuf::from)
         .unwrap_or_else(|| std::env::current_dir().expect("No current path"));
 
+    verbosity::set(verbosity::Verbosity::from_flags(matches.occurrences_of("verbose"), matches.is_present("quiet")));
+
+    // Colors are disabled if the output is not a terminal unless requested otherwise. A broken
+    // configuration must not keep commands like completions from working, so the defaults are used
+    match matches.value_of("color") {
+        Some(color) => color.parse::<config::ColorMode>()?.apply(),
+        None => match config::Config::load() {
+            Ok(config) => config.color.apply(),
+            Err(err) => {
+                config::Config::default().color.apply();
+                warn!("{}, using the default colors", err);
+            }
+        },
+    }
+
     let result = match matches.subcommand() {
         ("init", Some(matches)) => commands::init::run(&workspace, matches),
         ("update", Some(matches)) => commands::update::run(&workspace, matches),
         ("status", Some(matches)) => commands::status::run(&workspace, matches),
+        ("check", Some(matches)) => commands::check::run(&workspace, matches),
+        ("list", Some(matches)) => commands::list::run(&workspace, matches),
+        ("grep", Some(matches)) => commands::grep::run(&workspace, matches),
         ("edit", Some(matches)) => commands::edit::run(&workspace, matches),
         ("execute", Some(matches)) => commands::execute::run(&workspace, matches),
+        ("resume", Some(matches)) => commands::resume::run(&workspace, matches),
+        ("ignore", Some(matches)) => commands::ignore::run(&workspace, matches),
+        ("undo", Some(matches)) => commands::undo::run(&workspace, matches),
+        ("sub", Some(matches)) => commands::sub::run(&workspace, matches),
+        ("template", Some(matches)) => commands::template::run(&workspace, matches),
+        ("renumber", Some(matches)) => commands::renumber::run(&workspace, matches),
+        ("transform", Some(matches)) => commands::transform::run(&workspace, matches),
+        ("dedupe", Some(matches)) => commands::dedupe::run(&workspace, matches),
+        ("diff-dirs", Some(matches)) => commands::diff_dirs::run(matches),
+        ("set", Some(matches)) => commands::set::run(&workspace, matches),
+        ("rm", Some(matches)) => commands::rm::run(&workspace, matches),
+        ("keep", Some(matches)) => commands::keep::run(&workspace, matches),
+        ("import", Some(matches)) => commands::import::run(&workspace, matches),
+        ("reverse", Some(matches)) => commands::reverse::run(&workspace, matches),
+        ("abort", Some(matches)) => commands::abort::run(&workspace, matches),
+        ("stash", Some(matches)) => commands::stash::run(&workspace, matches),
+        ("completions", Some(matches)) => commands::completions::run(&workspace, matches),
+        ("man", Some(matches)) => commands::man::run(&workspace, matches),
+        (name, Some(matches)) => plugins::run(&workspace, name, matches.values_of_os("").into_iter().flatten())
+            .map_err(ProgramError::from),
         _ => unreachable!()
     };
 
@@ -100,14 +189,27 @@ fn main() -> Result<(), anyhow::Error> {
         }
 
         Err(ProgramError::NotInitialized) => {
-            eprintln!("{} {}", Paint::red("Not initialized."), "Use mmv init to do so");
+            eprintln!("{} Use mmv init to do so", Paint::red("Not initialized."));
             std::process::exit(exitcode::DATAERR);
         }
         Err(ProgramError::NotClean) => {
-            eprintln!("{} {}", Paint::red("Not clean."), "Use mvv edit ro correct your changeset");
+            eprintln!("{} Use mmv edit to correct your changeset", Paint::red("Not clean."));
+            std::process::exit(exitcode::DATAERR);
+        }
+        Err(ProgramError::Invalid) => {
+            eprintln!("{} Use mmv edit to correct your changeset", Paint::red("Invalid change set."));
             std::process::exit(exitcode::DATAERR);
         }
 
+        Err(ProgramError::Interrupted) => {
+            eprintln!("{} Use mmv execute to resume the remaining records", Paint::red("Interrupted."));
+            std::process::exit(130);
+        }
+
+        Err(ProgramError::Changed) => {
+            std::process::exit(1);
+        }
+
         Err(ProgramError::InternalError(err)) => {
             return Err(err);
         }
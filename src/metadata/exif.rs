@@ -0,0 +1,81 @@
+use std::cell::OnceCell;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+
+use crate::metadata::format_datetime;
+use crate::template::Variables;
+
+/// The variables read from the EXIF data of a photo.
+///
+/// Provides `{exif.date}` (the original capture time, formatted like `{exif.date:%Y/%m}`),
+/// `{exif.make}` and `{exif.model}`.
+pub struct ExifVariables<'a> {
+    path: &'a Path,
+
+    exif: OnceCell<Option<::exif::Exif>>,
+}
+
+impl<'a> ExifVariables<'a> {
+    pub fn new(path: &'a Path) -> Self {
+        return Self {
+            path,
+            exif: OnceCell::new(),
+        };
+    }
+
+    fn exif(&self) -> Option<&::exif::Exif> {
+        return self.exif.get_or_init(|| {
+            let file = File::open(self.path).ok()?;
+            return ::exif::Reader::new()
+                .read_from_container(&mut BufReader::new(file))
+                .ok();
+        }).as_ref();
+    }
+
+    fn ascii(&self, tag: ::exif::Tag) -> Option<&[u8]> {
+        let field = self.exif()?.get_field(tag, ::exif::In::PRIMARY)?;
+        return match &field.value {
+            ::exif::Value::Ascii(values) => values.first().map(Vec::as_slice),
+            _ => None,
+        };
+    }
+}
+
+impl Variables for ExifVariables<'_> {
+    fn lookup(&self, key: &str, format: Option<&str>) -> Result<Option<String>> {
+        match key {
+            "exif.date" => {
+                // Fall back to the modification time recorded by the camera if the capture time is
+                // missing
+                let value = self.ascii(::exif::Tag::DateTimeOriginal)
+                    .or_else(|| self.ascii(::exif::Tag::DateTime))
+                    .ok_or_else(|| anyhow!("No EXIF date in {}", self.path.display()))?;
+
+                let datetime = ::exif::DateTime::from_ascii(value)?;
+                let datetime = NaiveDate::from_ymd_opt(datetime.year as i32, datetime.month as u32, datetime.day as u32)
+                    .and_then(|date| date.and_hms_opt(datetime.hour as u32, datetime.minute as u32, datetime.second as u32))
+                    .ok_or_else(|| anyhow!("Invalid EXIF date in {}", self.path.display()))?;
+
+                return Ok(Some(format_datetime(datetime, format)?));
+            }
+
+            "exif.make" | "exif.model" => {
+                let tag = if key == "exif.make" { ::exif::Tag::Make } else { ::exif::Tag::Model };
+
+                let value = self.ascii(tag)
+                    .ok_or_else(|| anyhow!("No EXIF {} in {}", tag, self.path.display()))?;
+
+                // Tag values must not introduce additional path components
+                return Ok(Some(String::from_utf8_lossy(value).trim().replace('/', "_")));
+            }
+
+            _ => {
+                return Ok(None);
+            }
+        }
+    }
+}
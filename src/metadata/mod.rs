@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDateTime};
+
+#[cfg(any(feature = "exif", feature = "tags"))]
+use crate::escape::escape;
+use crate::template::Variables;
+
+#[cfg(feature = "exif")]
+mod exif;
+
+#[cfg(feature = "tags")]
+mod tags;
+
+/// The variables derived from the metadata of a source file.
+///
+/// The metadata is loaded lazily from the file when a related placeholder is used for the first
+/// time.
+pub struct MetadataVariables<'a> {
+    path: &'a Path,
+
+    #[cfg(feature = "exif")]
+    exif: exif::ExifVariables<'a>,
+
+    #[cfg(feature = "tags")]
+    tags: tags::TagVariables<'a>,
+}
+
+impl<'a> MetadataVariables<'a> {
+    pub fn new(path: &'a Path) -> Self {
+        return Self {
+            path,
+
+            #[cfg(feature = "exif")]
+            exif: exif::ExifVariables::new(path),
+
+            #[cfg(feature = "tags")]
+            tags: tags::TagVariables::new(path),
+        };
+    }
+}
+
+impl Variables for MetadataVariables<'_> {
+    fn lookup(&self, key: &str, format: Option<&str>) -> Result<Option<String>> {
+        if key == "mtime" {
+            let mtime = std::fs::metadata(self.path)
+                .and_then(|metadata| metadata.modified())
+                .map_err(|err| anyhow!("Failed to read modification time of {}: {}", self.path.display(), err))?;
+
+            let mtime = DateTime::<Local>::from(mtime).naive_local();
+
+            return Ok(Some(format_datetime(mtime, format)?));
+        }
+
+        // Values read from the file are escaped like the path variables, so the rendered target
+        // is unescaped into exactly these values
+        #[cfg(feature = "exif")]
+        if let Some(value) = self.exif.lookup(key, format)? {
+            return Ok(Some(escape(Path::new(&value))));
+        }
+
+        #[cfg(feature = "tags")]
+        if let Some(value) = self.tags.lookup(key, format)? {
+            return Ok(Some(escape(Path::new(&value))));
+        }
+
+        return Ok(None);
+    }
+}
+
+/// Formats a timestamp using a strftime-like format, defaulting to an ISO date.
+pub fn format_datetime(datetime: NaiveDateTime, format: Option<&str>) -> Result<String> {
+    let format = format.unwrap_or("%Y-%m-%d");
+
+    let items = StrftimeItems::new(format).collect::<Vec<_>>();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return Err(anyhow!("Invalid date format: {}", format));
+    }
+
+    return Ok(datetime.format_with_items(items.into_iter()).to_string());
+}
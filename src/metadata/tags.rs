@@ -0,0 +1,182 @@
+use std::cell::OnceCell;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use id3::TagLike;
+
+use crate::template::{format_number, Variables};
+
+/// The normalized tags of an audio file.
+#[derive(Debug, Clone, Default)]
+struct Tags {
+    artist: Option<String>,
+    album: Option<String>,
+    title: Option<String>,
+    genre: Option<String>,
+    track: Option<u32>,
+    year: Option<i32>,
+}
+
+impl Tags {
+    fn read(path: &Path) -> Option<Self> {
+        let extension = path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase);
+
+        return match extension.as_deref() {
+            Some("flac") => read_flac_comments(path).map(|comments| Self::from_vorbis(&comments)),
+            Some("ogg") | Some("oga") => read_ogg_comments(path).map(|comments| Self::from_vorbis(&comments)),
+            _ => id3::Tag::read_from_path(path).ok().map(|tag| Self::from_id3(&tag)),
+        };
+    }
+
+    fn from_id3(tag: &id3::Tag) -> Self {
+        return Self {
+            artist: tag.artist().map(str::to_string),
+            album: tag.album().map(str::to_string),
+            title: tag.title().map(str::to_string),
+            genre: tag.genre_parsed().map(|genre| genre.into_owned()),
+            track: tag.track(),
+            year: tag.year(),
+        };
+    }
+
+    fn from_vorbis(comments: &[(String, String)]) -> Self {
+        let get = |key: &str| comments.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.clone());
+
+        return Self {
+            artist: get("ARTIST"),
+            album: get("ALBUM"),
+            title: get("TITLE"),
+            genre: get("GENRE"),
+            // Track numbers are sometimes given as `3/12`
+            track: get("TRACKNUMBER")
+                .and_then(|track| track.split('/').next()?.trim().parse().ok()),
+            year: get("DATE")
+                .and_then(|date| date.get(..4)?.parse().ok()),
+        };
+    }
+}
+
+/// The variables read from the tags of an audio file (ID3 or Vorbis comments).
+///
+/// Provides `{artist}`, `{album}`, `{title}`, `{genre}`, `{year}` and `{track}` (optionally
+/// zero padded like `{track:02}`).
+pub struct TagVariables<'a> {
+    path: &'a Path,
+
+    tags: OnceCell<Option<Tags>>,
+}
+
+impl<'a> TagVariables<'a> {
+    pub fn new(path: &'a Path) -> Self {
+        return Self {
+            path,
+            tags: OnceCell::new(),
+        };
+    }
+
+    fn tags(&self) -> Option<&Tags> {
+        return self.tags.get_or_init(|| Tags::read(self.path)).as_ref();
+    }
+}
+
+impl Variables for TagVariables<'_> {
+    fn lookup(&self, key: &str, format: Option<&str>) -> Result<Option<String>> {
+        if !matches!(key, "artist" | "album" | "title" | "genre" | "track" | "year") {
+            return Ok(None);
+        }
+
+        let tags = self.tags()
+            .ok_or_else(|| anyhow!("No tags in {}", self.path.display()))?;
+
+        let value = match key {
+            "artist" => tags.artist.clone(),
+            "album" => tags.album.clone(),
+            "title" => tags.title.clone(),
+            "genre" => tags.genre.clone(),
+            "year" => tags.year.map(|year| year.to_string()),
+            "track" => tags.track.map(|track| format_number(track as usize, format)).transpose()?,
+            _ => unreachable!(),
+        };
+
+        let value = value
+            .ok_or_else(|| anyhow!("No {} tag in {}", key, self.path.display()))?;
+
+        // Tag values must not introduce additional path components
+        return Ok(Some(value.replace('/', "_")));
+    }
+}
+
+/// Reads the Vorbis comments from the metadata blocks of a FLAC file.
+fn read_flac_comments(path: &Path) -> Option<Vec<(String, String)>> {
+    let mut file = BufReader::new(File::open(path).ok()?);
+
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic).ok()?;
+    if &magic != b"fLaC" {
+        return None;
+    }
+
+    loop {
+        let mut header = [0u8; 4];
+        file.read_exact(&mut header).ok()?;
+
+        let last = header[0] & 0x80 != 0;
+        let kind = header[0] & 0x7f;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+
+        if kind == 4 {
+            let mut block = vec![0u8; length as usize];
+            file.read_exact(&mut block).ok()?;
+            return parse_vorbis_comments(&block);
+        }
+
+        if last {
+            return None;
+        }
+
+        file.seek(SeekFrom::Current(length as i64)).ok()?;
+    }
+}
+
+/// Parses a Vorbis comment block (without framing bit).
+fn parse_vorbis_comments(mut data: &[u8]) -> Option<Vec<(String, String)>> {
+    fn read_u32(data: &mut &[u8]) -> Option<u32> {
+        let (value, rest) = (data.get(..4)?, data.get(4..)?);
+        *data = rest;
+        return Some(u32::from_le_bytes([value[0], value[1], value[2], value[3]]));
+    }
+
+    fn read_string(data: &mut &[u8]) -> Option<String> {
+        let length = read_u32(data)? as usize;
+        let (value, rest) = (data.get(..length)?, data.get(length..)?);
+        *data = rest;
+        return Some(String::from_utf8_lossy(value).into_owned());
+    }
+
+    let _vendor = read_string(&mut data)?;
+
+    let count = read_u32(&mut data)?;
+    let mut comments = Vec::new();
+    for _ in 0..count {
+        let comment = read_string(&mut data)?;
+        if let Some((key, value)) = comment.split_once('=') {
+            comments.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    return Some(comments);
+}
+
+/// Reads the Vorbis comments from an Ogg Vorbis file.
+fn read_ogg_comments(path: &Path) -> Option<Vec<(String, String)>> {
+    let file = BufReader::new(File::open(path).ok()?);
+    let reader = lewton::inside_ogg::OggStreamReader::new(file).ok()?;
+
+    return Some(reader.comment_hdr.comment_list);
+}
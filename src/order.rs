@@ -0,0 +1,171 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use clap::Arg;
+
+use crate::changeset::Action;
+
+/// The order of the records in the change set files, which determines the record IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Order {
+    /// Ordered by path, comparing names byte by byte
+    #[default]
+    Path,
+
+    /// Ordered by path, comparing numbers in names by their value
+    Natural,
+
+    /// Grouped by file extension, ordered by path within each group
+    Extension,
+
+    /// Ordered by file size, smallest first
+    Size,
+
+    /// Ordered by modification time, oldest first
+    Mtime,
+
+    /// Grouped by directory, listing the entries of a directory before descending into its
+    /// subdirectories
+    Directory,
+}
+
+impl std::str::FromStr for Order {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "path" => Ok(Order::Path),
+            "natural" => Ok(Order::Natural),
+            "extension" => Ok(Order::Extension),
+            "size" => Ok(Order::Size),
+            "mtime" => Ok(Order::Mtime),
+            "directory" => Ok(Order::Directory),
+            _ => Err(anyhow!("Invalid order: {}", s)),
+        };
+    }
+}
+
+impl std::fmt::Display for Order {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Order::Path => write!(f, "path"),
+            Order::Natural => write!(f, "natural"),
+            Order::Extension => write!(f, "extension"),
+            Order::Size => write!(f, "size"),
+            Order::Mtime => write!(f, "mtime"),
+            Order::Directory => write!(f, "directory"),
+        };
+    }
+}
+
+impl Order {
+    pub const NAMES: &'static [&'static str] = &["path", "natural", "extension", "size", "mtime", "directory"];
+
+    pub fn arg() -> Arg<'static, 'static> {
+        return Arg::with_name("sort")
+            .long("sort")
+            .value_name("ORDER")
+            .possible_values(Self::NAMES)
+            .help("The order of the records in the change set, which is kept for the workspace")
+            .takes_value(true);
+    }
+
+    /// Whether the order depends on the metadata of the files, which may change between exporting
+    /// and importing the change set.
+    pub fn is_volatile(self) -> bool {
+        return matches!(self, Order::Size | Order::Mtime);
+    }
+
+    /// Returns the records in this order.
+    ///
+    /// Sizes and modification times are read from the sources in `root`, records with the same key
+    /// or missing sources are ordered by path.
+    pub fn sort<'a>(self, root: &Path, records: &'a BTreeMap<PathBuf, Action>) -> Vec<(&'a PathBuf, &'a Action)> {
+        let mut records = records.iter().collect::<Vec<_>>();
+
+        // The sort is stable, so records with equal keys stay in path order
+        match self {
+            Order::Path => {}
+            Order::Natural => records.sort_by(|(a, _), (b, _)| natural_cmp(a, b)),
+            Order::Extension => records.sort_by_key(|(path, _)| path.extension().map(OsStr::to_os_string)),
+            Order::Size => records.sort_by_cached_key(|(path, _)| root.join(path).symlink_metadata()
+                .map(|metadata| metadata.len())
+                .unwrap_or_default()),
+            Order::Mtime => records.sort_by_cached_key(|(path, _)| root.join(path).symlink_metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()),
+            Order::Directory => records.sort_by(|(a, _), (b, _)| directory_cmp(a, b)),
+        }
+
+        return records;
+    }
+}
+
+/// Compares two paths component by component, treating runs of digits as numbers.
+///
+/// Numbers with the same value are ordered by their count of leading zeros and names which are
+/// equal otherwise fall back to the byte order, so the order is total.
+pub fn natural_cmp(a: &Path, b: &Path) -> Ordering {
+    let mut a_components = a.components();
+    let mut b_components = b.components();
+
+    loop {
+        let ordering = match (a_components.next(), b_components.next()) {
+            (Some(a), Some(b)) => natural_cmp_name(a.as_os_str().as_bytes(), b.as_os_str().as_bytes()),
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (None, None) => return a.cmp(b),
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Compares two paths by their parent directory first, so all entries of a directory are
+/// adjacent.
+fn directory_cmp(a: &Path, b: &Path) -> Ordering {
+    return a.parent().cmp(&b.parent())
+        .then_with(|| a.cmp(b));
+}
+
+fn natural_cmp_name(a: &[u8], b: &[u8]) -> Ordering {
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() && j < b.len() {
+        if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let (a_start, b_start) = (i, j);
+            while i < a.len() && a[i].is_ascii_digit() { i += 1; }
+            while j < b.len() && b[j].is_ascii_digit() { j += 1; }
+
+            let a_number = trim_zeros(&a[a_start..i]);
+            let b_number = trim_zeros(&b[b_start..j]);
+
+            let ordering = a_number.len().cmp(&b_number.len())
+                .then_with(|| a_number.cmp(b_number))
+                .then_with(|| (i - a_start).cmp(&(j - b_start)));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        } else {
+            if a[i] != b[j] {
+                return a[i].cmp(&b[j]);
+            }
+
+            i += 1;
+            j += 1;
+        }
+    }
+
+    return (a.len() - i).cmp(&(b.len() - j));
+}
+
+fn trim_zeros(number: &[u8]) -> &[u8] {
+    let zeros = number.iter().take_while(|&&digit| digit == b'0').count();
+    return &number[zeros..];
+}
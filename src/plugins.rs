@@ -0,0 +1,27 @@
+use std::ffi::OsStr;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{anyhow, Result};
+
+/// Runs the external subcommand `name` by replacing this process with the executable `mmv-<name>`
+/// found on `PATH`.
+///
+/// The plugin gets the remaining arguments and the absolute path of the workspace as
+/// `$MMV_WORKSPACE`. This only returns if the plugin could not be executed.
+pub fn run<'a>(workspace: &Path, name: &str, args: impl IntoIterator<Item=&'a OsStr>) -> Result<()> {
+    let program = format!("mmv-{}", name);
+    debug!("running {}", program);
+
+    let err = Command::new(&program)
+        .args(args)
+        .env("MMV_WORKSPACE", std::path::absolute(workspace)?)
+        .exec();
+
+    if err.kind() == std::io::ErrorKind::NotFound {
+        return Err(anyhow!("Unknown command: {} (no {} found on PATH)", name, program));
+    }
+
+    return Err(anyhow!("Failed to run {}: {}", program, err));
+}
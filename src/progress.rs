@@ -0,0 +1,86 @@
+use std::io::IsTerminal;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use yansi::Paint;
+
+use crate::verbosity::{self, Verbosity};
+
+/// Reports the progress of an execution.
+///
+/// If stdout is a terminal, a progress bar with the transferred bytes, the throughput and the
+/// remaining time replaces the line printed for each file. Only failed operations are printed
+/// then. Running verbosely keeps the lines, running quietly prints failed operations only.
+pub struct Progress {
+    bar: Option<ProgressBar>,
+
+    records: u64,
+    total: u64,
+}
+
+impl Progress {
+    pub fn new(records: u64, bytes: u64) -> Self {
+        let bar = if std::io::stdout().is_terminal() && verbosity::get() == Verbosity::Normal {
+            let bar = ProgressBar::new(bytes);
+            bar.set_style(ProgressStyle::with_template("{wide_bar} {binary_bytes}/{binary_total_bytes} {binary_bytes_per_sec} ETA {eta} {msg}")
+                .expect("Invalid progress template"));
+            bar.set_message(format!("0/{} records", records));
+            Some(bar)
+        } else {
+            None
+        };
+
+        return Self {
+            bar,
+            records: 0,
+            total: records,
+        };
+    }
+
+    /// Reports a successful operation on `bytes` bytes described by `line`.
+    ///
+    /// The record is counted as completed if `last` is set.
+    pub fn succeeded(&mut self, line: &str, bytes: u64, last: bool) {
+        if last {
+            self.records += 1;
+        }
+
+        match &self.bar {
+            Some(bar) => {
+                bar.inc(bytes);
+                bar.set_message(format!("{}/{} records", self.records, self.total));
+            }
+            None => info!("{} {}", line, Paint::green("✓").bold()),
+        }
+    }
+
+    /// Reports a failed operation described by `line`.
+    pub fn failed(&mut self, line: &str) {
+        let line = format!("{} {}", line, Paint::red("✗").bold());
+
+        match &self.bar {
+            Some(bar) => bar.println(line),
+            None => println!("{}", line),
+        }
+    }
+
+    /// Prints a message without breaking the progress bar.
+    pub fn message(&self, message: &str) {
+        match &self.bar {
+            Some(bar) => bar.suspend(|| eprintln!("{}", message)),
+            None => eprintln!("{}", message),
+        }
+    }
+
+    /// Prints a warning without breaking the progress bar unless running quietly.
+    pub fn warning(&self, warning: &str) {
+        if verbosity::enabled(Verbosity::Normal) {
+            self.message(&format!("{} {}", Paint::yellow("⚠").bold(), Paint::yellow(warning)));
+        }
+    }
+
+    pub fn finish(self) {
+        if let Some(bar) = self.bar {
+            bar.finish();
+        }
+    }
+}
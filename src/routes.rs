@@ -0,0 +1,92 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobMatcher};
+
+/// Routes the records of the change set to their target directories.
+///
+/// Each rule maps a glob pattern matched against the source of a record to a target directory. The
+/// first matching rule wins, records not matching any rule are moved to the default target
+/// directory.
+#[derive(Debug, Clone)]
+pub struct Router {
+    default: PathBuf,
+    rules: Vec<(GlobMatcher, PathBuf)>,
+}
+
+impl Router {
+    pub fn new(default: impl Into<PathBuf>) -> Self {
+        return Self {
+            default: default.into(),
+            rules: Vec::new(),
+        };
+    }
+
+    /// Adds a rule given as `GLOB=DIR`.
+    pub fn add(&mut self, rule: &str) -> Result<()> {
+        let (pattern, dir) = rule.split_once('=')
+            .filter(|(pattern, dir)| !pattern.is_empty() && !dir.is_empty())
+            .ok_or_else(|| anyhow!("Invalid route: {}", rule))?;
+
+        self.rules.push((Glob::new(pattern)?.compile_matcher(), PathBuf::from(dir)));
+
+        return Ok(());
+    }
+
+    /// Adds the rules from a file containing one rule per line.
+    ///
+    /// Empty lines and lines starting with `#` are skipped.
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let rules = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to read routes {}: {}", path.display(), err))?;
+
+        for rule in rules.lines().map(str::trim) {
+            if rule.is_empty() || rule.starts_with('#') {
+                continue;
+            }
+
+            self.add(rule)?;
+        }
+
+        return Ok(());
+    }
+
+    /// The target directory of records not matching any rule.
+    pub fn default(&self) -> &Path {
+        return &self.default;
+    }
+
+    /// Returns the target directory for the record with the given source.
+    pub fn dir(&self, source: &Path) -> &Path {
+        return self.rules.iter()
+            .find(|(matcher, _)| matcher.is_match(source))
+            .map_or(&self.default, |(_, dir)| dir);
+    }
+
+    /// Returns the full target path of the record with the given source and target.
+    pub fn resolve(&self, source: &Path, path: &Path) -> PathBuf {
+        return self.dir(source).join(path);
+    }
+
+    /// Returns the normalized absolute target path of the record, which allows comparing targets
+    /// ending up at the same location.
+    pub fn locate(&self, source: &Path, path: &Path) -> Result<PathBuf> {
+        return Ok(normalize(&std::path::absolute(self.resolve(source, path))?));
+    }
+}
+
+/// Resolves `.` and `..` components of an absolute path lexically.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::ParentDir => {
+                result.pop();
+            }
+            std::path::Component::CurDir => {}
+            component => result.push(component),
+        }
+    }
+
+    return result;
+}
@@ -0,0 +1,643 @@
+use std::fs::{File, FileType};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use clap::{Arg, ArgMatches};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::Gitignore;
+use ignore::WalkBuilder;
+
+use crate::changeset::Workspace;
+use crate::config::{FilterConfig, WorkspaceConfig};
+use crate::units::{parse_size, parse_time};
+
+/// Defines how symlinks in the workspace are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkMode {
+    /// Symlinks are not listed
+    #[default]
+    Skip,
+
+    /// Symlinks are listed and recreated at the target
+    Preserve,
+
+    /// Symlinks are followed and the file they point to is copied to the target
+    Follow,
+}
+
+impl std::fmt::Display for SymlinkMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            SymlinkMode::Skip => write!(f, "skip"),
+            SymlinkMode::Preserve => write!(f, "preserve"),
+            SymlinkMode::Follow => write!(f, "follow"),
+        };
+    }
+}
+
+impl std::str::FromStr for SymlinkMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "skip" => Ok(SymlinkMode::Skip),
+            "preserve" => Ok(SymlinkMode::Preserve),
+            "follow" => Ok(SymlinkMode::Follow),
+            _ => Err(anyhow!("Invalid symlink mode: {}", s)),
+        };
+    }
+}
+
+/// Filters restricting the files picked up by scanning the workspace.
+///
+/// The filter is persisted in the workspace on `init` so that `update` applies the same filter
+/// unless a new one is specified.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+
+    /// Directories listed as single records instead of their content
+    dirs: Vec<String>,
+
+    /// Whether to list empty directories as records
+    empty_dirs: bool,
+
+    symlinks: SymlinkMode,
+
+    /// Whether to list FIFOs as records, which are recreated at the target
+    recreate_fifos: bool,
+
+    respect_gitignore: bool,
+
+    min_depth: Option<usize>,
+    max_depth: Option<usize>,
+
+    /// Whether to include hidden files and directories - included if not specified
+    hidden: Option<bool>,
+
+    /// File extensions to include (lower case, without leading dot)
+    extensions: Vec<String>,
+
+    /// Size limits in bytes
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+
+    /// Modification time limits, kept as given to re-evaluate relative times on every scan
+    newer_than: Option<String>,
+    older_than: Option<String>,
+}
+
+impl ScanFilter {
+    /// Creates the filter from the command line arguments or returns `None` if no filter was given.
+    pub fn from_matches(matches: &ArgMatches) -> Result<Option<Self>> {
+        let values = |name| matches.values_of(name)
+            .map(|values| values.map(str::to_string).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let depth = |name| matches.value_of(name)
+            .map(|value| value.parse::<usize>()
+                .map_err(|_| anyhow!("Invalid depth: {}", value)))
+            .transpose();
+
+        let size = |name| matches.value_of(name)
+            .map(parse_size)
+            .transpose();
+
+        let time = |name| matches.value_of(name)
+            .map(|value| parse_time(value).map(|_| value.to_string()))
+            .transpose();
+
+        let filter = Self {
+            include: values("include"),
+            exclude: values("exclude"),
+            dirs: values("dir"),
+            empty_dirs: matches.is_present("empty-dirs"),
+            recreate_fifos: matches.is_present("recreate-fifos"),
+            symlinks: matches.value_of("symlinks")
+                .map(str::parse)
+                .transpose()?
+                .unwrap_or_default(),
+            respect_gitignore: matches.is_present("respect-gitignore"),
+            min_depth: depth("min-depth")?,
+            max_depth: depth("max-depth")?,
+            extensions: values("ext").into_iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .collect(),
+            min_size: size("min-size")?,
+            max_size: size("max-size")?,
+            newer_than: time("newer-than")?,
+            older_than: time("older-than")?,
+            hidden: if matches.is_present("hidden") {
+                Some(true)
+            } else if matches.is_present("no-hidden") {
+                Some(false)
+            } else {
+                None
+            },
+        };
+
+        if filter == Self::default() {
+            return Ok(None);
+        }
+
+        // Validate patterns early
+        filter.matcher()?;
+
+        return Ok(Some(filter));
+    }
+
+    /// Creates the filter from the `[filter]` section of the workspace configuration.
+    pub fn from_config(config: &FilterConfig) -> Result<Self> {
+        let filter = Self {
+            include: config.include.clone(),
+            exclude: config.exclude.clone(),
+            dirs: config.dir.clone(),
+            empty_dirs: config.empty_dirs,
+            symlinks: config.symlinks.as_deref()
+                .map(str::parse)
+                .transpose()?
+                .unwrap_or_default(),
+            recreate_fifos: config.recreate_fifos,
+            respect_gitignore: config.respect_gitignore,
+            min_depth: config.min_depth,
+            max_depth: config.max_depth,
+            hidden: config.hidden,
+            extensions: config.ext.iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .collect(),
+            min_size: config.min_size.as_deref().map(parse_size).transpose()?,
+            max_size: config.max_size.as_deref().map(parse_size).transpose()?,
+            newer_than: config.newer_than.as_deref()
+                .map(|value| parse_time(value).map(|_| value.to_string()))
+                .transpose()?,
+            older_than: config.older_than.as_deref()
+                .map(|value| parse_time(value).map(|_| value.to_string()))
+                .transpose()?,
+        };
+
+        filter.matcher()?;
+
+        return Ok(filter);
+    }
+
+    /// The filter used if none was given, taken from the workspace configuration.
+    pub fn configured(workspace: &Workspace) -> Result<Self> {
+        return match WorkspaceConfig::load(workspace)?.filter {
+            Some(config) => Self::from_config(&config),
+            None => Ok(Self::default()),
+        };
+    }
+
+    /// Loads the filter given on the last `init` or `update`, falling back to the configured one.
+    pub fn load(workspace: &Workspace) -> Result<Self> {
+        let path = workspace.filter_path();
+        if !path.is_file() {
+            return Self::configured(workspace);
+        }
+
+        let mut filter = Self::default();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let (key, value) = line.split_once(' ')
+                .unwrap_or((line.as_str(), ""));
+
+            match key {
+                "include" => filter.include.push(value.to_string()),
+                "exclude" => filter.exclude.push(value.to_string()),
+                "dir" => filter.dirs.push(value.to_string()),
+                "empty-dirs" => filter.empty_dirs = true,
+                "symlinks" => filter.symlinks = value.parse()?,
+                "recreate-fifos" => filter.recreate_fifos = true,
+                "respect-gitignore" => filter.respect_gitignore = true,
+                "min-depth" => filter.min_depth = Some(value.parse()?),
+                "max-depth" => filter.max_depth = Some(value.parse()?),
+                "hidden" => filter.hidden = Some(value.parse()?),
+                "ext" => filter.extensions = value.split(',').map(str::to_string).collect(),
+                "min-size" => filter.min_size = Some(value.parse()?),
+                "max-size" => filter.max_size = Some(value.parse()?),
+                "newer-than" => filter.newer_than = Some(value.to_string()),
+                "older-than" => filter.older_than = Some(value.to_string()),
+                _ => return Err(anyhow!("Invalid filter: {}", line)),
+            }
+        }
+
+        return Ok(filter);
+    }
+
+    pub fn save(&self, workspace: &Workspace) -> Result<()> {
+        let mut file = File::create(workspace.filter_path())?;
+
+        for pattern in self.include.iter() {
+            writeln!(file, "include {}", pattern)?;
+        }
+
+        for pattern in self.exclude.iter() {
+            writeln!(file, "exclude {}", pattern)?;
+        }
+
+        for pattern in self.dirs.iter() {
+            writeln!(file, "dir {}", pattern)?;
+        }
+
+        if self.empty_dirs {
+            writeln!(file, "empty-dirs")?;
+        }
+
+        if self.symlinks != SymlinkMode::Skip {
+            writeln!(file, "symlinks {}", self.symlinks)?;
+        }
+
+        if self.recreate_fifos {
+            writeln!(file, "recreate-fifos")?;
+        }
+
+        if self.respect_gitignore {
+            writeln!(file, "respect-gitignore")?;
+        }
+
+        if let Some(depth) = self.min_depth {
+            writeln!(file, "min-depth {}", depth)?;
+        }
+
+        if let Some(depth) = self.max_depth {
+            writeln!(file, "max-depth {}", depth)?;
+        }
+
+        if let Some(hidden) = self.hidden {
+            writeln!(file, "hidden {}", hidden)?;
+        }
+
+        if !self.extensions.is_empty() {
+            writeln!(file, "ext {}", self.extensions.join(","))?;
+        }
+
+        if let Some(size) = self.min_size {
+            writeln!(file, "min-size {}", size)?;
+        }
+
+        if let Some(size) = self.max_size {
+            writeln!(file, "max-size {}", size)?;
+        }
+
+        if let Some(time) = self.newer_than.as_ref() {
+            writeln!(file, "newer-than {}", time)?;
+        }
+
+        if let Some(time) = self.older_than.as_ref() {
+            writeln!(file, "older-than {}", time)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Resolves the filter to use: the one given on the command line, which is persisted for
+    /// subsequent scans, or the persisted one.
+    pub fn resolve(workspace: &Workspace, matches: &ArgMatches) -> Result<Self> {
+        if let Some(filter) = Self::from_matches(matches)? {
+            filter.save(workspace)?;
+            return Ok(filter);
+        }
+
+        return Self::load(workspace);
+    }
+
+    pub fn symlinks(&self) -> SymlinkMode {
+        return self.symlinks;
+    }
+
+    fn matcher(&self) -> Result<ScanMatcher> {
+        let build = |patterns: &[String]| -> Result<GlobSet> {
+            let mut set = GlobSetBuilder::new();
+            for pattern in patterns {
+                set.add(Glob::new(pattern)?);
+            }
+
+            return Ok(set.build()?);
+        };
+
+        return Ok(ScanMatcher {
+            include: if self.include.is_empty() { None } else { Some(build(&self.include)?) },
+            exclude: build(&self.exclude)?,
+            dirs: build(&self.dirs)?,
+            extensions: self.extensions.clone(),
+        });
+    }
+
+    pub fn args() -> Vec<Arg<'static, 'static>> {
+        return vec![
+            Arg::with_name("include")
+                .long("include")
+                .value_name("GLOB")
+                .help("Only include files matching the pattern (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+            Arg::with_name("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .help("Exclude files matching the pattern (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+            Arg::with_name("dir")
+                .long("dir")
+                .value_name("GLOB")
+                .help("List directories matching the pattern as single records instead of their files (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+            Arg::with_name("empty-dirs")
+                .long("empty-dirs")
+                .help("List empty directories as records")
+                .takes_value(false),
+            Arg::with_name("symlinks")
+                .long("symlinks")
+                .value_name("MODE")
+                .help("Skip symlinks, preserve them as links or follow them to copy the files they point to")
+                .takes_value(true)
+                .possible_values(&["skip", "preserve", "follow"]),
+            Arg::with_name("recreate-fifos")
+                .long("recreate-fifos")
+                .help("List FIFOs as records and recreate them at the target instead of skipping them")
+                .takes_value(false),
+            Arg::with_name("respect-gitignore")
+                .long("respect-gitignore")
+                .help("Exclude files ignored by git if the workspace is a git repository")
+                .takes_value(false),
+            Arg::with_name("min-depth")
+                .long("min-depth")
+                .value_name("N")
+                .help("Only include files at least N levels below the workspace (top level is 1)")
+                .takes_value(true),
+            Arg::with_name("max-depth")
+                .long("max-depth")
+                .value_name("N")
+                .help("Only include files at most N levels below the workspace (top level is 1)")
+                .takes_value(true),
+            Arg::with_name("hidden")
+                .long("hidden")
+                .help("Include hidden files and directories (default)")
+                .takes_value(false)
+                .conflicts_with("no-hidden"),
+            Arg::with_name("no-hidden")
+                .long("no-hidden")
+                .help("Skip hidden files and directories")
+                .takes_value(false),
+            Arg::with_name("ext")
+                .long("ext")
+                .value_name("EXT,...")
+                .help("Only include files with one of the given extensions")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true),
+            Arg::with_name("min-size")
+                .long("min-size")
+                .value_name("SIZE")
+                .help("Only include files of at least the given size (i.e. 10M)")
+                .takes_value(true),
+            Arg::with_name("max-size")
+                .long("max-size")
+                .value_name("SIZE")
+                .help("Only include files of at most the given size (i.e. 10M)")
+                .takes_value(true),
+            Arg::with_name("newer-than")
+                .long("newer-than")
+                .value_name("TIME")
+                .help("Only include files modified after the given date (i.e. 2020-01-31) or age (i.e. 7d)")
+                .takes_value(true),
+            Arg::with_name("older-than")
+                .long("older-than")
+                .value_name("TIME")
+                .help("Only include files modified before the given date (i.e. 2020-01-31) or age (i.e. 7d)")
+                .takes_value(true),
+        ];
+    }
+}
+
+/// The compiled form of a `ScanFilter`.
+struct ScanMatcher {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+
+    dirs: GlobSet,
+
+    extensions: Vec<String>,
+}
+
+impl ScanMatcher {
+    fn is_match(set: &GlobSet, path: &Path) -> bool {
+        return set.is_match(path) || path.file_name()
+            .map(|name| set.is_match(name))
+            .unwrap_or(false);
+    }
+
+    /// Checks if a directory is listed as a single record.
+    fn is_unit(&self, path: &Path) -> bool {
+        return Self::is_match(&self.dirs, path) && !self.excludes(path);
+    }
+
+    fn excludes(&self, path: &Path) -> bool {
+        return Self::is_match(&self.exclude, path);
+    }
+
+    fn accepts(&self, path: &Path) -> bool {
+        if !self.extensions.is_empty() {
+            let extension = path.extension()
+                .and_then(|ext| ext.to_str())
+                .map(str::to_lowercase);
+
+            match extension {
+                Some(extension) if self.extensions.contains(&extension) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(include) = self.include.as_ref() {
+            if !Self::is_match(include, path) {
+                return false;
+            }
+        }
+
+        return !Self::is_match(&self.exclude, path);
+    }
+}
+
+/// Checks if a file is a FIFO, socket or device node.
+pub fn is_special(file_type: FileType) -> bool {
+    return file_type.is_fifo() || file_type.is_socket() || file_type.is_block_device() || file_type.is_char_device();
+}
+
+/// Checks if a directory does not contain any entries.
+fn is_empty_dir(path: &Path) -> bool {
+    return std::fs::read_dir(path)
+        .map(|mut entries| entries.next().is_none())
+        .unwrap_or(false);
+}
+
+/// Loads the `.mmvignore` file (gitignore syntax) from the root of the workspace.
+fn load_mmvignore(workdir: &Path) -> Result<Gitignore> {
+    let path = workdir.join(".mmvignore");
+    if !path.is_file() {
+        return Ok(Gitignore::empty());
+    }
+
+    let (gitignore, err) = Gitignore::new(path);
+    if let Some(err) = err {
+        return Err(err.into());
+    }
+
+    return Ok(gitignore);
+}
+
+pub fn scan_tree<'a>(workdir: impl AsRef<Path> + 'a, filter: &ScanFilter) -> Result<impl Iterator<Item=PathBuf> + 'a> {
+    let matcher = Arc::new(filter.matcher()?);
+    let mmvignore = load_mmvignore(workdir.as_ref())?;
+
+    let respect_gitignore = filter.respect_gitignore;
+    let empty_dirs = filter.empty_dirs;
+    let symlinks = filter.symlinks;
+    let recreate_fifos = filter.recreate_fifos;
+    let min_depth = filter.min_depth.unwrap_or(1);
+    let (min_size, max_size) = (filter.min_size, filter.max_size);
+    let newer_than = filter.newer_than.as_deref().map(parse_time).transpose()?;
+    let older_than = filter.older_than.as_deref().map(parse_time).transpose()?;
+
+    let root = workdir.as_ref().to_path_buf();
+    let units = matcher.clone();
+
+    let mut walker = WalkBuilder::new(workdir.as_ref());
+    walker
+        .standard_filters(false)
+        .git_ignore(respect_gitignore)
+        .git_exclude(respect_gitignore)
+        .git_global(respect_gitignore)
+        .parents(respect_gitignore)
+        .hidden(filter.hidden == Some(false))
+        .max_depth(filter.max_depth)
+        .follow_links(symlinks == SymlinkMode::Follow)
+        .sort_by_file_name(Ord::cmp)
+        // Ignored directories are pruned without descending into them
+        .filter_entry(move |entry| {
+            // The repository itself is never part of the tracked files
+            if respect_gitignore && entry.file_name() == ".git" {
+                return false;
+            }
+
+            // The content of directories listed as single records is not listed
+            if entry.depth() > min_depth {
+                let parent = entry.path().parent()
+                    .and_then(|parent| parent.strip_prefix(&root).ok());
+                if parent.is_some_and(|parent| units.is_unit(parent)) {
+                    return false;
+                }
+            }
+
+            let is_dir = entry.file_type()
+                .map(|file_type| file_type.is_dir())
+                .unwrap_or(false);
+            return !mmvignore.matched(entry.path(), is_dir).is_ignore();
+        });
+
+    return Ok(walker.build()
+        .filter_map(|entry| {
+            match entry {
+                Ok(entry) => {
+                    return Some(entry);
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return None;
+                }
+            }
+        })
+        .filter_map(move |entry| {
+            if entry.depth() < min_depth {
+                return None;
+            }
+
+            // The path is absolute. The common prefix is removed to make the path relative to the
+            // working directory
+            let path = entry.path().strip_prefix(workdir.as_ref())
+                .expect("Path not relative");
+
+            // Only list files and directories listed as single records or being empty
+            let dir = entry.depth() > 0
+                && entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false);
+            let unit = dir && (matcher.is_unit(path) || (empty_dirs && is_empty_dir(entry.path()) && !matcher.excludes(path)));
+
+            // Special files can not be copied and are reported instead of being silently skipped,
+            // except for FIFOs which can be recreated
+            let fifo = recreate_fifos && entry.file_type().is_some_and(|file_type| file_type.is_fifo());
+            if !fifo && entry.file_type().is_some_and(is_special) {
+                if matcher.accepts(path) {
+                    warn!("{}: skipping special file", path.display());
+                }
+
+                return None;
+            }
+
+            // Followed symlinks are reported as the file they point to
+            let file = fifo || entry.file_type()
+                .map(|file_type| file_type.is_file() || (symlinks == SymlinkMode::Preserve && file_type.is_symlink()))
+                .unwrap_or(false);
+            if !unit && !file {
+                return None;
+            }
+
+            if !unit && (min_size.is_some() || max_size.is_some() || newer_than.is_some() || older_than.is_some()) {
+                let metadata = match entry.metadata() {
+                    Ok(metadata) => metadata,
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        return None;
+                    }
+                };
+
+                let size = metadata.len();
+                if min_size.map(|min| size < min).unwrap_or(false) || max_size.map(|max| size > max).unwrap_or(false) {
+                    verbose!("skipping {}: size out of range", path.display());
+                    return None;
+                }
+
+                if newer_than.is_some() || older_than.is_some() {
+                    let mtime = match metadata.modified() {
+                        Ok(mtime) => mtime,
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            return None;
+                        }
+                    };
+
+                    if newer_than.map(|time| mtime < time).unwrap_or(false) || older_than.map(|time| mtime > time).unwrap_or(false) {
+                        verbose!("skipping {}: modification time out of range", path.display());
+                        return None;
+                    }
+                }
+            }
+
+            // Ignore mmv status files
+            if entry.file_name().to_str()
+                .map(|s| s.starts_with(".mmv"))
+                .unwrap_or(false) {
+                return None;
+            }
+
+            if !unit && !matcher.accepts(path) {
+                verbose!("skipping {}: excluded by filter", path.display());
+                return None;
+            }
+
+            // Directories are marked by a trailing slash
+            if unit {
+                let mut path = path.as_os_str().to_os_string();
+                path.push("/");
+                return Some(PathBuf::from(path));
+            }
+
+            return Some(path.to_path_buf());
+        }));
+}
@@ -0,0 +1,196 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use crate::escape::escape;
+
+/// A part of a parsed template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder { key: String, format: Option<String> },
+}
+
+/// A template for target paths.
+///
+/// Placeholders are written as `{key}` or `{key:format}` and are substituted by looking up the key
+/// in the variables passed to `render`. Literal braces are written as `{{` and `}}`.
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl std::str::FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(anyhow!("Unterminated placeholder: {}", s)),
+                        }
+                    }
+
+                    let (key, format) = match placeholder.find(':') {
+                        Some(i) => (&placeholder[..i], Some(placeholder[i + 1..].to_string())),
+                        None => (placeholder.as_str(), None),
+                    };
+
+                    if key.is_empty() {
+                        return Err(anyhow!("Empty placeholder: {}", s));
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+
+                    segments.push(Segment::Placeholder { key: key.to_string(), format });
+                }
+
+                '}' => {
+                    return Err(anyhow!("Unmatched '}}': {}", s));
+                }
+
+                c => {
+                    literal.push(c);
+                }
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        return Ok(Self { segments });
+    }
+}
+
+impl Template {
+    pub fn render(&self, variables: &dyn Variables) -> Result<String> {
+        let mut result = String::new();
+
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Literal(literal) => {
+                    result.push_str(literal);
+                }
+
+                Segment::Placeholder { key, format } => {
+                    let value = variables.lookup(key, format.as_deref())?
+                        .ok_or_else(|| anyhow!("Unknown placeholder: {}", key))?;
+                    result.push_str(&value);
+                }
+            }
+        }
+
+        return Ok(result);
+    }
+}
+
+/// A source of values for template placeholders.
+pub trait Variables {
+    /// Returns the formatted value for the given key or `None` if the key is unknown.
+    fn lookup(&self, key: &str, format: Option<&str>) -> Result<Option<String>>;
+}
+
+/// The variables derived from the path of a source file.
+///
+/// Provides `{path}`, `{name}` (the file name without extension), `{ext}`, `{filename}`,
+/// `{parent}` and `{counter}`.
+pub struct PathVariables<'a> {
+    path: &'a Path,
+    counter: usize,
+}
+
+impl<'a> PathVariables<'a> {
+    pub fn new(path: &'a Path, counter: usize) -> Self {
+        return Self {
+            path,
+            counter,
+        };
+    }
+}
+
+impl Variables for PathVariables<'_> {
+    fn lookup(&self, key: &str, format: Option<&str>) -> Result<Option<String>> {
+        let value = match key {
+            "path" => escape(self.path),
+            "name" => self.path.file_stem()
+                .map(|stem| escape(Path::new(stem)))
+                .unwrap_or_default(),
+            "ext" => self.path.extension()
+                .map(|ext| escape(Path::new(ext)))
+                .unwrap_or_default(),
+            "filename" => self.path.file_name()
+                .map(|name| escape(Path::new(name)))
+                .unwrap_or_default(),
+            "parent" => self.path.parent()
+                .filter(|parent| parent != &Path::new(""))
+                .map(escape)
+                .unwrap_or_else(|| String::from(".")),
+            "counter" => return Ok(Some(format_number(self.counter, format)?)),
+            _ => return Ok(None),
+        };
+
+        return Ok(Some(value));
+    }
+}
+
+/// Formats a number using a width specification like `3` or `03` (zero padded).
+pub fn format_number(value: usize, format: Option<&str>) -> Result<String> {
+    let format = match format {
+        Some(format) => format,
+        None => return Ok(value.to_string()),
+    };
+
+    let width = format.parse::<usize>()
+        .map_err(|_| anyhow!("Invalid number format: {}", format))?;
+
+    if format.starts_with('0') {
+        return Ok(format!("{:0width$}", value, width = width));
+    } else {
+        return Ok(format!("{:width$}", value, width = width));
+    }
+}
+
+/// Chains multiple variable sources, the first source knowing a key wins.
+pub struct Chain<'a> {
+    sources: Vec<&'a dyn Variables>,
+}
+
+impl<'a> Chain<'a> {
+    pub fn new(sources: Vec<&'a dyn Variables>) -> Self {
+        return Self { sources };
+    }
+}
+
+impl Variables for Chain<'_> {
+    fn lookup(&self, key: &str, format: Option<&str>) -> Result<Option<String>> {
+        for source in self.sources.iter() {
+            if let Some(value) = source.lookup(key, format)? {
+                return Ok(Some(value));
+            }
+        }
+
+        return Ok(None);
+    }
+}
@@ -0,0 +1,61 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+/// Limits the throughput of all copies sharing the throttle.
+pub struct Throttle {
+    /// Bytes per second
+    rate: u64,
+
+    /// The point in time until which the transferred bytes are accounted for
+    next: Mutex<Instant>,
+}
+
+impl Throttle {
+    pub fn new(rate: u64) -> Self {
+        return Self {
+            rate,
+            next: Mutex::new(Instant::now()),
+        };
+    }
+
+    /// Accounts for `bytes` being transferred and blocks until the rate allows the transfer.
+    fn consume(&self, bytes: usize) {
+        let now = Instant::now();
+
+        let wait = {
+            let mut next = self.next.lock().expect("Throttle poisoned");
+
+            // Idle times are not saved up for later bursts
+            *next = (*next).max(now) + Duration::from_secs_f64(bytes as f64 / self.rate as f64);
+            *next - now
+        };
+
+        std::thread::sleep(wait);
+    }
+
+    /// Copies the content and permissions of a file without exceeding the rate.
+    pub fn copy(&self, source: &Path, target: &Path) -> Result<()> {
+        let mut reader = File::open(source)?;
+        let mut writer = File::create(target)?;
+
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let len = reader.read(&mut buffer)?;
+            if len == 0 {
+                break;
+            }
+
+            self.consume(len);
+            writer.write_all(&buffer[..len])?;
+        }
+
+        writer.set_permissions(reader.metadata()?.permissions())?;
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+
+/// Parses a size with an optional binary suffix (`K`, `M`, `G`, `T`), i.e. `10M`.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(i) => (&s[..i], &s[i..]),
+        None => (s, ""),
+    };
+
+    let factor: u64 = match unit.to_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(anyhow!("Invalid size: {}", s)),
+    };
+
+    let number = number.parse::<f64>()
+        .map_err(|_| anyhow!("Invalid size: {}", s))?;
+
+    return Ok((number * factor as f64) as u64);
+}
+
+/// Parses a point in time given as absolute date (`2020-01-31` or `2020-01-31 12:00:00`) or as
+/// age relative to now (`30m`, `12h`, `7d`, `2w`).
+pub fn parse_time(s: &str) -> Result<SystemTime> {
+    let s = s.trim();
+
+    if let Some(i) = s.find(|c: char| !c.is_ascii_digit()) {
+        let (number, unit) = (&s[..i], &s[i..]);
+        let factor = match unit {
+            "s" => Some(1),
+            "m" => Some(60),
+            "h" => Some(60 * 60),
+            "d" => Some(60 * 60 * 24),
+            "w" => Some(60 * 60 * 24 * 7),
+            _ => None,
+        };
+
+        if let (Ok(number), Some(factor)) = (number.parse::<u64>(), factor) {
+            return SystemTime::now().checked_sub(Duration::from_secs(number * factor))
+                .ok_or_else(|| anyhow!("Invalid time: {}", s));
+        }
+    }
+
+    let datetime = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map(|date| date.and_hms_opt(0, 0, 0).expect("Invalid time")))
+        .map_err(|_| anyhow!("Invalid time: {}", s))?;
+
+    let datetime = Local.from_local_datetime(&datetime)
+        .earliest()
+        .ok_or_else(|| anyhow!("Invalid time: {}", s))?;
+
+    return Ok(datetime.into());
+}
@@ -0,0 +1,83 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much is printed besides the requested output and errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only errors
+    Quiet,
+
+    /// Status messages and warnings
+    Normal,
+
+    /// Details about each record, like skipped files and how files are copied
+    Verbose,
+
+    /// Tracing of the individual operations
+    Debug,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+impl Verbosity {
+    pub fn from_flags(verbose: u64, quiet: bool) -> Self {
+        return match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Debug,
+        };
+    }
+}
+
+pub fn set(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn get() -> Verbosity {
+    return match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        2 => Verbosity::Verbose,
+        _ => Verbosity::Debug,
+    };
+}
+
+pub fn enabled(verbosity: Verbosity) -> bool {
+    return get() >= verbosity;
+}
+
+/// Prints a status message to stdout unless running quietly.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::verbosity::enabled($crate::verbosity::Verbosity::Normal) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Prints a warning to stderr unless running quietly.
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::verbosity::enabled($crate::verbosity::Verbosity::Normal) {
+            eprintln!("{} {}", ::yansi::Paint::yellow("⚠").bold(), ::yansi::Paint::yellow(format!($($arg)*)));
+        }
+    };
+}
+
+/// Prints details to stderr if running verbosely.
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::verbosity::enabled($crate::verbosity::Verbosity::Verbose) {
+            eprintln!("{}", ::yansi::Paint::new(format!($($arg)*)).dimmed());
+        }
+    };
+}
+
+/// Prints tracing output to stderr if debugging.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::verbosity::enabled($crate::verbosity::Verbosity::Debug) {
+            eprintln!("{} {}", ::yansi::Paint::magenta("debug").dimmed(), ::yansi::Paint::new(format!($($arg)*)).dimmed());
+        }
+    };
+}